@@ -0,0 +1,259 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::Result;
+use regex::Regex;
+
+const EDITORCONFIG: &str = ".editorconfig";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+	Tab,
+	Space,
+}
+
+/// Properties resolved from .editorconfig files for a single file. Unset
+/// properties are None.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Properties {
+	pub indent_style: Option<IndentStyle>,
+	pub indent_size: Option<u32>,
+	pub tab_width: Option<u32>,
+	pub trim_trailing_whitespace: Option<bool>,
+	pub insert_final_newline: Option<bool>,
+}
+
+impl Properties {
+	/// Returns the width of an indentation level, falling back to tab_width when
+	/// indent_size is unset or "tab".
+	pub fn tab_size(&self) -> Option<u32> {
+		match self.indent_style {
+			Some(IndentStyle::Tab) => self.tab_width.or(self.indent_size),
+			_ => self.indent_size.or(self.tab_width),
+		}
+	}
+	fn set(&mut self, key: &str, value: &str) {
+		let value = value.to_lowercase();
+		let unset = value == "unset";
+		match key {
+			"indent_style" => {
+				self.indent_style = match value.as_str() {
+					"tab" => Some(IndentStyle::Tab),
+					"space" => Some(IndentStyle::Space),
+					_ => None,
+				}
+			}
+			// "tab" means use tab_width, which is what a None here does.
+			"indent_size" => self.indent_size = value.parse().ok(),
+			"tab_width" => self.tab_width = value.parse().ok(),
+			"trim_trailing_whitespace" => {
+				self.trim_trailing_whitespace = if unset { None } else { parse_bool(&value) }
+			}
+			"insert_final_newline" => {
+				self.insert_final_newline = if unset { None } else { parse_bool(&value) }
+			}
+			_ => {}
+		}
+	}
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+	match s {
+		"true" => Some(true),
+		"false" => Some(false),
+		_ => None,
+	}
+}
+
+/// Resolves the .editorconfig properties for path by reading every .editorconfig
+/// file from path's directory up to the filesystem root or the first file with
+/// root = true. Closer files take precedence.
+pub fn properties(path: &Path) -> Result<Properties> {
+	let mut files = vec![];
+	for dir in path.ancestors().skip(1) {
+		let contents = match read_to_string(dir.join(EDITORCONFIG)) {
+			Ok(c) => c,
+			Err(_) => continue,
+		};
+		let root = is_root(&contents);
+		files.push((dir, contents));
+		if root {
+			break;
+		}
+	}
+	let mut props = Properties::default();
+	for (dir, contents) in files.iter().rev() {
+		let rel = match path.strip_prefix(dir) {
+			Ok(rel) => rel,
+			Err(_) => continue,
+		};
+		apply(&mut props, contents, &rel.to_string_lossy())?;
+	}
+	Ok(props)
+}
+
+fn is_root(contents: &str) -> bool {
+	for line in contents.lines() {
+		let line = line.trim();
+		if line.starts_with('[') {
+			return false;
+		}
+		if let Some((k, v)) = split_pair(line) {
+			if k == "root" && v.eq_ignore_ascii_case("true") {
+				return true;
+			}
+		}
+	}
+	false
+}
+
+fn split_pair(line: &str) -> Option<(String, &str)> {
+	let mut sp = line.splitn(2, '=');
+	let k = sp.next()?.trim().to_lowercase();
+	let v = sp.next()?.trim();
+	Some((k, v))
+}
+
+/// Applies the sections of a single .editorconfig file that match rel, the file
+/// path relative to the .editorconfig's directory.
+fn apply(props: &mut Properties, contents: &str, rel: &str) -> Result<()> {
+	let mut matched = false;
+	for line in contents.lines() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+			continue;
+		}
+		if line.starts_with('[') && line.ends_with(']') {
+			matched = glob_to_regex(&line[1..line.len() - 1])?.is_match(rel);
+			continue;
+		}
+		if !matched {
+			continue;
+		}
+		if let Some((k, v)) = split_pair(line) {
+			props.set(&k, v);
+		}
+	}
+	Ok(())
+}
+
+/// Converts an editorconfig section glob into an anchored regex over paths
+/// relative to the .editorconfig file. Globs without a slash match the file
+/// name in any directory.
+fn glob_to_regex(glob: &str) -> Result<Regex> {
+	let (glob, prefix) = if let Some(g) = glob.strip_prefix('/') {
+		(g, "")
+	} else if glob.contains('/') {
+		(glob, "")
+	} else {
+		(glob, "(?:.*/)?")
+	};
+	let mut re = format!("^{}", prefix);
+	let chars: Vec<char> = glob.chars().collect();
+	let mut braces = 0;
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		match c {
+			'*' => {
+				if chars.get(i + 1) == Some(&'*') {
+					re.push_str(".*");
+					i += 1;
+				} else {
+					re.push_str("[^/]*");
+				}
+			}
+			'?' => re.push_str("[^/]"),
+			'[' => match chars[i..].iter().position(|&c| c == ']') {
+				Some(end) => {
+					let class: String = chars[i + 1..i + end].iter().collect();
+					let class = match class.strip_prefix('!') {
+						Some(c) => format!("^{}", c),
+						None => class,
+					};
+					re.push_str(&format!("[{}]", class));
+					i += end;
+				}
+				None => re.push_str(r"\["),
+			},
+			'{' => {
+				braces += 1;
+				re.push_str("(?:");
+			}
+			'}' if braces > 0 => {
+				braces -= 1;
+				re.push(')');
+			}
+			',' if braces > 0 => re.push('|'),
+			'\\' if i + 1 < chars.len() => {
+				i += 1;
+				re.push_str(&regex::escape(&chars[i].to_string()));
+			}
+			_ => re.push_str(&regex::escape(&c.to_string())),
+		}
+		i += 1;
+	}
+	re.push('$');
+	Ok(Regex::new(&re)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::editorconfig::*;
+
+	#[test]
+	fn globs() {
+		let cases = vec![
+			("*", "a.py", true),
+			("*", "dir/a.py", true),
+			("*.py", "dir/a.py", true),
+			("*.py", "a.pyc", false),
+			("*.{js,ts}", "src/a.ts", true),
+			("*.{js,ts}", "src/a.rs", false),
+			("Makefile", "sub/Makefile", true),
+			("lib/**.js", "lib/a/b.js", true),
+			("lib/*.js", "lib/a/b.js", false),
+			("/top.txt", "top.txt", true),
+			("/top.txt", "a/top.txt", false),
+			("[!a]b", "cb", true),
+			("[!a]b", "ab", false),
+		];
+		for (glob, path, expect) in cases {
+			assert_eq!(
+				glob_to_regex(glob).unwrap().is_match(path),
+				expect,
+				"{} {}",
+				glob,
+				path
+			);
+		}
+	}
+
+	#[test]
+	fn apply_sections() {
+		let contents = "
+root = true
+
+[*]
+indent_style = tab
+tab_width = 8
+insert_final_newline = true
+
+# Python is special.
+[*.py]
+indent_style = space
+indent_size = 4
+";
+		assert!(is_root(contents));
+		let mut p = Properties::default();
+		apply(&mut p, contents, "src/main.py").unwrap();
+		assert_eq!(p.indent_style, Some(IndentStyle::Space));
+		assert_eq!(p.tab_size(), Some(4));
+		assert_eq!(p.insert_final_newline, Some(true));
+		let mut p = Properties::default();
+		apply(&mut p, contents, "main.go").unwrap();
+		assert_eq!(p.indent_style, Some(IndentStyle::Tab));
+		assert_eq!(p.tab_size(), Some(8));
+		assert_eq!(p.trim_trailing_whitespace, None);
+	}
+}
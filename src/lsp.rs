@@ -129,10 +129,94 @@ impl Client {
 								],
 							},
 						}),
+						is_preferred_support: Some(true),
+						disabled_support: Some(true),
+						..Default::default()
+					}),
+					completion: Some(CompletionClientCapabilities {
+						completion_item: Some(CompletionItemCapability {
+							resolve_support: Some(CompletionItemCapabilityResolveSupport {
+								properties: vec![
+									"additionalTextEdits".to_string(),
+									"command".to_string(),
+									"detail".to_string(),
+									"documentation".to_string(),
+								],
+							}),
+							insert_replace_support: Some(true),
+							snippet_support: Some(true),
+							..Default::default()
+						}),
+						..Default::default()
+					}),
+					synchronization: Some(TextDocumentSyncClientCapabilities {
+						will_save: Some(true),
+						will_save_wait_until: Some(true),
+						did_save: Some(true),
+						..Default::default()
+					}),
+					signature_help: Some(SignatureHelpClientCapabilities {
+						signature_information: Some(SignatureInformationSettings {
+							documentation_format: None,
+							parameter_information: Some(ParameterInformationSettings {
+								label_offset_support: Some(true),
+							}),
+							active_parameter_support: Some(true),
+						}),
+						context_support: Some(true),
+						..Default::default()
+					}),
+					document_link: Some(DocumentLinkClientCapabilities {
+						dynamic_registration: None,
+						tooltip_support: Some(true),
+					}),
+					document_symbol: Some(DocumentSymbolClientCapabilities {
+						hierarchical_document_symbol_support: Some(true),
+						..Default::default()
+					}),
+					definition: Some(GotoCapability {
+						link_support: Some(true),
+						..Default::default()
+					}),
+					declaration: Some(GotoCapability {
+						link_support: Some(true),
+						..Default::default()
+					}),
+					implementation: Some(GotoCapability {
+						link_support: Some(true),
+						..Default::default()
+					}),
+					type_definition: Some(GotoCapability {
+						link_support: Some(true),
+						..Default::default()
+					}),
+					inlay_hint: Some(InlayHintClientCapabilities {
+						resolve_support: Some(InlayHintResolveClientCapabilities {
+							properties: vec!["tooltip".to_string(), "label.tooltip".to_string()],
+						}),
+						..Default::default()
+					}),
+					diagnostic: Some(DiagnosticClientCapabilities {
+						related_document_support: Some(true),
 						..Default::default()
 					}),
 					..Default::default()
 				}),
+				window: Some(WindowClientCapabilities {
+					show_document: Some(ShowDocumentClientCapabilities { support: true }),
+					..Default::default()
+				}),
+				workspace: Some(WorkspaceClientCapabilities {
+					apply_edit: Some(true),
+					diagnostic: Some(DiagnosticWorkspaceClientCapabilities {
+						refresh_support: Some(true),
+					}),
+					inlay_hint: Some(InlayHintWorkspaceClientCapabilities {
+						refresh_support: Some(true),
+					}),
+					symbol: Some(WorkspaceSymbolClientCapabilities::default()),
+					..Default::default()
+				}),
 				..Default::default()
 			},
 			trace: None,
@@ -166,6 +250,18 @@ impl Client {
 		write!(self.stdin, "{}", s)?;
 		Ok(())
 	}
+	/// Sends result as the response to the server request id.
+	pub fn respond<R: serde::Serialize>(&mut self, id: usize, result: R) -> Result<()> {
+		let msg = ResponseMessage {
+			jsonrpc: "2.0",
+			id,
+			result,
+		};
+		let s = serde_json::to_string(&msg)?;
+		let s = format!("Content-Length: {}\r\n\r\n{}", s.len(), s);
+		write!(self.stdin, "{}", s)?;
+		Ok(())
+	}
 	fn new_id(&mut self) -> Result<usize> {
 		let id = self.next_id;
 		self.next_id += 1;
@@ -187,6 +283,13 @@ struct RequestMessage<P> {
 	params: P,
 }
 
+#[derive(serde::Serialize)]
+struct ResponseMessage<R> {
+	jsonrpc: &'static str,
+	id: usize,
+	result: R,
+}
+
 #[derive(serde::Serialize)]
 struct NotificationMessage<P> {
 	jsonrpc: &'static str,
@@ -4,10 +4,12 @@ use std::convert::TryInto;
 use std::fmt::Write;
 use std::fs::{metadata, read_to_string};
 use std::io::Read;
+use std::path::Path;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Error, Result};
-use crossbeam_channel::{bounded, Receiver, Select};
+use crossbeam_channel::{bounded, tick, Receiver, Select, Sender};
 use diff;
 use lazy_static::lazy_static;
 use lsp_types::{notification::*, request::*, *};
@@ -18,11 +20,25 @@ use serde_json::Value;
 
 use plan9::{acme::*, plumb};
 
+mod editorconfig;
 mod lsp;
+mod markdown;
+mod snippet;
 
 #[derive(Deserialize)]
 struct TomlConfig {
 	servers: HashMap<String, ConfigServer>,
+	hover: Option<ConfigHover>,
+}
+
+/// Limits on what's listed at the top of the acre window for the focused file.
+#[derive(Clone, Default, Deserialize)]
+struct ConfigHover {
+	/// code actions, completions, and lenses.
+	actions: Option<usize>,
+	completions: Option<usize>,
+	/// lines each of hover and signature help text.
+	lines: Option<usize>,
 }
 
 #[derive(Clone, Deserialize)]
@@ -35,7 +51,67 @@ struct ConfigServer {
 	options: Option<Value>,
 	actions_on_put: Option<Vec<CodeActionKind>>,
 	format_on_put: Option<bool>,
+	/// apply formatting edits before Put writes the file, by taking over the
+	/// window's event file.
+	format_before_put: Option<bool>,
 	env: Option<HashMap<String, String>>,
+	completion_mode: Option<CompletionMode>,
+	formatting: Option<ConfigFormatting>,
+	/// Project URI -> formatting overrides for files under that URI.
+	projects: Option<HashMap<String, ConfigProject>>,
+}
+
+/// Which range of an insert/replace completion edit to use.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CompletionMode {
+	/// Insert the completion, keeping any text after the cursor.
+	Insert,
+	/// Replace the whole word at the cursor with the completion.
+	Replace,
+}
+
+#[derive(Clone, Default, Deserialize)]
+struct ConfigFormatting {
+	tab_size: Option<u32>,
+	insert_spaces: Option<bool>,
+	trim_trailing_whitespace: Option<bool>,
+	insert_final_newline: Option<bool>,
+	trim_final_newlines: Option<bool>,
+}
+
+impl ConfigFormatting {
+	/// Returns self with any unset fields taken from other.
+	fn or(self, other: ConfigFormatting) -> ConfigFormatting {
+		ConfigFormatting {
+			tab_size: self.tab_size.or(other.tab_size),
+			insert_spaces: self.insert_spaces.or(other.insert_spaces),
+			trim_trailing_whitespace: self
+				.trim_trailing_whitespace
+				.or(other.trim_trailing_whitespace),
+			insert_final_newline: self.insert_final_newline.or(other.insert_final_newline),
+			trim_final_newlines: self.trim_final_newlines.or(other.trim_final_newlines),
+		}
+	}
+}
+
+impl From<editorconfig::Properties> for ConfigFormatting {
+	fn from(p: editorconfig::Properties) -> Self {
+		ConfigFormatting {
+			tab_size: p.tab_size(),
+			insert_spaces: p
+				.indent_style
+				.map(|s| s == editorconfig::IndentStyle::Space),
+			trim_trailing_whitespace: p.trim_trailing_whitespace,
+			insert_final_newline: p.insert_final_newline,
+			trim_final_newlines: None,
+		}
+	}
+}
+
+#[derive(Clone, Deserialize)]
+struct ConfigProject {
+	formatting: Option<ConfigFormatting>,
 }
 
 fn main() -> Result<()> {
@@ -105,6 +181,96 @@ enum Action {
 	Command(CodeActionOrCommand),
 	Completion(CompletionItem),
 	CodeLens(CodeLens),
+	Link(DocumentLink),
+	/// Requests only code actions of a kind, or all of them for None.
+	Filter(Option<CodeActionKind>),
+}
+
+impl Action {
+	/// Reports whether running the action leaves the list in place: links so
+	/// others can still be opened, filters, and disabled code actions.
+	fn keeps_list(&self) -> bool {
+		match self {
+			Action::Link(_) | Action::Filter(_) => true,
+			Action::Command(CodeActionOrCommand::CodeAction(action)) => action.disabled.is_some(),
+			_ => false,
+		}
+	}
+}
+
+/// Results of a request sent with a partial result token, accumulated from
+/// $/progress notifications until the response arrives.
+struct PartialResult {
+	client_id: ClientId,
+	method: String,
+	url: Url,
+	/// requests for the same search, like Symbols sent to each server, share
+	/// a group so their results are shown together.
+	group: usize,
+	/// streamed locations, for references.
+	locations: Vec<Location>,
+	/// texts of the files in locations, so each is read once.
+	texts: HashMap<Url, String>,
+}
+
+/// A $/progress notification whose value isn't parsed yet, since partial
+/// results don't match ProgressParams.
+#[derive(Deserialize)]
+struct RawProgress {
+	token: NumberOrString,
+	value: Box<serde_json::value::RawValue>,
+}
+
+/// The steps run after Put in a file window before the file is written.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum SaveStep {
+	WillSaveWaitUntil,
+	Formatting,
+	Put,
+}
+
+/// How long a Put waits on each willSaveWaitUntil or formatting response.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A Put waiting on a willSaveWaitUntil or formatting response.
+struct PendingSave {
+	filename: String,
+	wid: usize,
+	/// the step to run once the response's edits are applied.
+	next: SaveStep,
+	/// when to give up on the response and write the file anyway.
+	deadline: Instant,
+}
+
+/// A window/showMessageRequest from a server, answered when the user clicks one
+/// of its actions or Get.
+struct MessageRequest {
+	client_name: String,
+	id: usize,
+	params: ShowMessageRequestParams,
+}
+
+/// Signature help for the cursor position in a window.
+struct SignatureState {
+	client_name: String,
+	url: Url,
+	filename: String,
+	id: usize,
+	/// dot when the request was sent.
+	dot: (u32, u32),
+	/// window body when the server was last sent it.
+	text: String,
+	/// the last non-empty response. While set, cursor movement in the window
+	/// retriggers the request.
+	help: Option<SignatureHelp>,
+}
+
+/// A window position saved before a jump.
+#[derive(Debug, PartialEq, Clone)]
+struct NavEntry {
+	filename: String,
+	/// dot as character offsets.
+	dot: (u32, u32),
 }
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
@@ -129,25 +295,57 @@ struct Server {
 	ws: HashMap<String, HashMap<usize, ServerWin>>,
 	/// Sorted Vec of ordered filenames for printing.
 	names: Vec<String>,
-	/// Vec of (position, win id) to map Look locations to windows.
+	/// Vec of (rune position, file name) to map Look locations to windows.
 	addr: Vec<(usize, Option<String>)>,
 	/// Holds the last winid of the focus'd filename.
 	focus_id: HashMap<String, usize>,
 	body: String,
 	output: String,
 	focus: String,
+	/// Name of the most recently focused file window.
+	file_focus: Option<String>,
+	/// Locations to return to with [back] and [forward].
+	nav_back: Vec<NavEntry>,
+	nav_forward: Vec<NavEntry>,
 	progress: HashMap<String, WDProgress>,
-	/// file name -> list of diagnostics
-	diags: BTreeMap<String, Vec<String>>,
+	/// file Url -> list of diagnostics
+	diags: BTreeMap<Url, Vec<Diagnostic>>,
+	/// file Url -> plumbable location of each diagnostic and of its related
+	/// information. Computed when the diagnostics are set, since it needs the
+	/// text of the files.
+	diag_locs: HashMap<Url, Vec<(String, Vec<String>)>>,
+	/// client name -> file Url -> result id of the last pulled diagnostics
+	diag_result_ids: HashMap<String, HashMap<Url, String>>,
 	/// request (client_name, id) -> (method, file Url)
 	requests: HashMap<ClientId, (String, Url)>,
 
 	/// current window info
 	current_hover: Option<WindowHover>,
+	/// the +Diagnostics window, if open
+	diag_win: Option<DiagWin>,
+	/// the +Hints window, if open
+	hints_win: Option<HintsWin>,
+	/// the +Folds window, if open
+	folds_win: Option<FoldsWin>,
+	/// open +Outline windows, one per file
+	outline_wins: Vec<OutlineWin>,
+	/// the most recently inserted snippet with tabstops
+	snippet: Option<SnippetSession>,
+	/// win id -> selection stack for [expand] and [shrink]
+	selections: HashMap<usize, SelectionStack>,
+	/// requests made for [expand] and [shrink] -> (filename, win id, shrink)
+	selection_requests: HashMap<ClientId, (String, usize, bool)>,
+	/// occurrences from the last [highlight]
+	highlights: Option<HighlightSession>,
+	/// the last signature help request, used to retrigger it as the cursor moves
+	signature: Option<SignatureState>,
 
 	log_r: Receiver<LogEvent>,
 	ev_r: Receiver<Event>,
 	err_r: Receiver<Error>,
+	/// events from auxiliary windows, with their win id
+	aux_s: Sender<(usize, Event)>,
+	aux_r: Receiver<(usize, Event)>,
 
 	/// client name -> client
 	clients: HashMap<String, lsp::Client>,
@@ -157,6 +355,30 @@ struct Server {
 	files: HashMap<String, String>,
 	/// list of LSP message IDs to auto-run actions
 	autorun: HashMap<usize, ()>,
+	/// requests made for [peek]: None for the definition request, the
+	/// definition's location for the follow up symbol request
+	peeks: HashMap<ClientId, Option<Location>>,
+	/// requests made while saving a file window
+	saves: HashMap<ClientId, PendingSave>,
+	/// windows written by acre after their pre-save edits, so their put event
+	/// doesn't format them again
+	presaved: HashSet<usize>,
+	/// partial result token -> results streamed so far
+	partials: HashMap<String, PartialResult>,
+	next_partial: usize,
+	/// partial result group -> symbol output lines received so far
+	partial_lines: HashMap<usize, Vec<String>>,
+	/// window/showMessageRequest requests awaiting a choice
+	message_requests: Vec<MessageRequest>,
+	/// (start, end, message_requests index, action index) character offsets of
+	/// message request actions in the body
+	message_addrs: Vec<(usize, usize, usize, usize)>,
+	/// requests made for +Outline windows
+	outline_requests: HashSet<ClientId>,
+	/// requests made for [doc] -> symbol name
+	docs: HashMap<ClientId, String>,
+	/// open [doc] windows
+	doc_wins: Vec<AuxWin>,
 }
 
 #[derive(Debug)]
@@ -171,15 +393,23 @@ struct WindowHover {
 	hover: Option<String>,
 	/// result of signature request
 	signature: Option<String>,
+	/// source of a peeked definition, with a header line
+	peek: Option<String>,
 	lens: Vec<CodeLens>,
+	/// document links in the file.
+	links: Vec<DocumentLink>,
 	/// completion response. we need to cache this because we also need the token
 	/// response to come, and we don't know which will come first.
 	completion: Vec<CompletionItem>,
-	code_actions: Vec<Action>,
+	code_actions: Vec<CodeActionOrCommand>,
+	/// range code actions were requested for.
+	range: Range,
+	/// kind chosen with [actions kind].
+	action_filter: Option<CodeActionKind>,
 
 	/// merged actions from the code action and completion requests
 	actions: Vec<Action>,
-	/// Vec of (position, index) into the vec of actions. The Option is None for
+	/// Vec of (rune position, index) into the vec of actions. The Option is None for
 	/// the last element.
 	action_addrs: Vec<(usize, Option<usize>)>,
 	/// cached output result of hover and actions
@@ -262,6 +492,752 @@ impl ServerWin {
 			.to_string();
 		Ok(line)
 	}
+	/// Returns the range of the word (letters, digits, and underscores) at the
+	/// cursor.
+	fn word_range(&mut self) -> Result<Range> {
+		let buf = self.body()?;
+		let pos = self.pos()?;
+		let nl = NlOffsets::new(buf.as_bytes())?;
+		let (line, col) = nl.offset_to_line(pos.0);
+		let chars: Vec<char> = buf
+			.lines()
+			.nth(line as usize)
+			.unwrap_or("")
+			.chars()
+			.collect();
+		let (start, end) = word_bounds(&chars, col as usize);
+		Ok(Range::new(
+			Position::new(line, start as u32),
+			Position::new(line, end as u32),
+		))
+	}
+	/// Sets dot to r and shows it.
+	fn select(&mut self, r: &Range) -> Result<()> {
+		let text = self.body()?;
+		select_in(&mut self.w, r, &text)
+	}
+	/// Returns the word at the cursor.
+	fn word(&mut self) -> Result<String> {
+		let range = self.word_range()?;
+		let buf = self.body()?;
+		Ok(buf
+			.lines()
+			.nth(range.start.line as usize)
+			.unwrap_or("")
+			.chars()
+			.skip(range.start.character as usize)
+			.take((range.end.character - range.start.character) as usize)
+			.collect())
+	}
+	/// Returns the snippet variables for an insertion at pos.
+	fn snippet_vars(&mut self, pos: Position) -> Result<snippet::Variables> {
+		let buf = self.body()?;
+		let line = buf.lines().nth(pos.line as usize).unwrap_or("");
+		let chars: Vec<char> = line.chars().collect();
+		let (start, end) = word_bounds(&chars, pos.character as usize);
+		Ok(snippet::Variables {
+			filepath: self.url.path().to_string(),
+			current_line: line.to_string(),
+			current_word: chars[start..end].iter().collect(),
+			line_index: pos.line,
+		})
+	}
+	fn body(&mut self) -> Result<String> {
+		let mut buf = String::new();
+		self.w.read(File::Body)?.read_to_string(&mut buf)?;
+		Ok(buf)
+	}
+}
+
+/// Returns the bounds of the word (letters, digits, and underscores) in line
+/// around col.
+fn word_bounds(line: &[char], col: usize) -> (usize, usize) {
+	let is_word = |c: &&char| c.is_alphanumeric() || **c == '_';
+	let col = col.min(line.len());
+	let start = col - line[..col].iter().rev().take_while(is_word).count();
+	let end = col + line[col..].iter().take_while(is_word).count();
+	(start, end)
+}
+
+/// An inserted snippet whose tabstops can be visited with [next] and [prev].
+struct SnippetSession {
+	url: Url,
+	/// (start, end) character offsets of the tabstops in the file.
+	tabstops: Vec<(u32, u32)>,
+	/// index of the selected tabstop.
+	current: usize,
+	/// file length in characters when the current tabstop was selected.
+	len: u32,
+}
+
+/// Occurrences of a symbol whose window's dot can be cycled through with [next]
+/// and [prev].
+struct HighlightSession {
+	url: Url,
+	/// sorted occurrence ranges.
+	ranges: Vec<Range>,
+	/// index of the selected occurrence, None until one is selected.
+	current: Option<usize>,
+}
+
+/// Nested selection ranges around a point, for [expand] and [shrink].
+struct SelectionStack {
+	/// ranges from innermost to outermost.
+	ranges: Vec<Range>,
+	/// index of the selected range.
+	current: usize,
+}
+
+/// An acme window other than the acre window whose body is owned by acre.
+struct AuxWin {
+	w: Win,
+	name: String,
+	/// cached body so unchanged windows aren't rewritten.
+	body: String,
+}
+
+impl AuxWin {
+	/// Creates a window named name with tag appended to its tag. Executing Get,
+	/// Del, or one of cmds in the window, or looking at text in it, sends the
+	/// event along with the win id to aux_s. Other commands are handled by acme.
+	fn new(
+		name: &str,
+		tag: &str,
+		cmds: &'static [&'static str],
+		aux_s: Sender<(usize, Event)>,
+	) -> Result<AuxWin> {
+		AuxWin::with_looks(name, tag, cmds, |_| true, aux_s)
+	}
+	/// Like new, but only sends looks at text for which looks returns true.
+	/// Acme handles the others, like searches.
+	fn with_looks(
+		name: &str,
+		tag: &str,
+		cmds: &'static [&'static str],
+		looks: fn(&str) -> bool,
+		aux_s: Sender<(usize, Event)>,
+	) -> Result<AuxWin> {
+		let mut w = Win::new()?;
+		w.name(name)?;
+		w.write(File::Tag, tag)?;
+		let id = w.id();
+		let mut wev = w.events()?;
+		thread::Builder::new()
+			.name(format!("AuxWindowEvents-{}", id))
+			.spawn(move || loop {
+				let mut ev = match wev.read_event() {
+					Ok(ev) => ev,
+					Err(_) => return,
+				};
+				match ev.c2 {
+					'x' | 'X' => {
+						let cmd = ev.text.split_whitespace().next().unwrap_or("");
+						if cmd == "Del" {
+							let _ = aux_s.send((id, ev));
+							return;
+						} else if cmd == "Get" || cmds.contains(&cmd) {
+							if aux_s.send((id, ev)).is_err() {
+								return;
+							}
+						} else {
+							wev.write_event(ev).unwrap();
+						}
+					}
+					'L' => {
+						ev.load_text();
+						if !looks(&ev.text) {
+							wev.write_event(ev).unwrap();
+						} else if aux_s.send((id, ev)).is_err() {
+							return;
+						}
+					}
+					_ => {}
+				}
+			})?;
+		Ok(AuxWin {
+			w,
+			name: name.to_string(),
+			body: "".to_string(),
+		})
+	}
+	fn set_body(&mut self, body: &str) -> Result<()> {
+		if self.body != body {
+			self.body = body.to_string();
+			self.w.write(File::Addr, ",")?;
+			self.w.write(File::Data, body)?;
+			self.w.ctl("clean")?;
+		}
+		Ok(())
+	}
+}
+
+/// Sends Put executed in file window id to aux_s and hands every other event
+/// back to acme.
+fn intercept_put(id: usize, mut wev: WinEvents, aux_s: Sender<(usize, Event)>) {
+	thread::Builder::new()
+		.name(format!("FileWindowEvents-{}", id))
+		.spawn(move || loop {
+			let ev = match wev.read_event() {
+				Ok(ev) => ev,
+				Err(_) => return,
+			};
+			let ok = match ev.c2 {
+				'x' | 'X' if ev.text == "Put" => aux_s.send((id, ev)).is_ok(),
+				'x' | 'X' | 'l' | 'L' => wev.write_event(ev).is_ok(),
+				_ => true,
+			};
+			if !ok {
+				return;
+			}
+		})
+		.unwrap();
+}
+
+/// Returns the argument of a tag command: the words following it, or else the
+/// chorded argument.
+fn cmd_arg(ev: &Event) -> String {
+	let arg = ev
+		.text
+		.split_whitespace()
+		.skip(1)
+		.collect::<Vec<_>>()
+		.join(" ");
+	if arg.is_empty() {
+		ev.arg.trim().to_string()
+	} else {
+		arg
+	}
+}
+
+/// Sets filter to the regex arg, or clears it if arg is empty. A bad regex keeps
+/// the old filter and returns the error to show.
+fn set_filter(filter: &mut Option<Regex>, arg: &str) -> Option<String> {
+	if arg.is_empty() {
+		*filter = None;
+		return None;
+	}
+	match Regex::new(arg) {
+		Ok(re) => {
+			*filter = Some(re);
+			None
+		}
+		Err(err) => Some(err.to_string()),
+	}
+}
+
+/// Returns the lines shown above an aux window's body for error.
+fn error_header(error: &Option<String>) -> String {
+	match error {
+		Some(err) => format!("{}\n\n", err),
+		None => "".to_string(),
+	}
+}
+
+impl Drop for AuxWin {
+	fn drop(&mut self) {
+		let _ = self.w.del(true);
+	}
+}
+
+/// The +Diagnostics window.
+struct DiagWin {
+	aux: AuxWin,
+	/// least severe diagnostic to show.
+	severity: DiagnosticSeverity,
+	/// if set, only show files whose path matches.
+	file: Option<Regex>,
+	/// error from the last command, like a bad regex.
+	error: Option<String>,
+}
+
+/// The +Hints window: a copy of a file with each line followed by its inlay
+/// hints.
+struct HintsWin {
+	aux: AuxWin,
+	client_name: String,
+	url: Url,
+	/// file contents when the hints were requested.
+	text: String,
+	hints: Vec<InlayHint>,
+	/// (start, end, hint index) character offsets of each hint line in the body.
+	lines: Vec<(usize, usize, usize)>,
+}
+
+impl HintsWin {
+	fn new(client_name: String, url: Url, aux_s: Sender<(usize, Event)>) -> Result<HintsWin> {
+		let aux = AuxWin::new("+Hints", " Get ", &[], aux_s)?;
+		Ok(HintsWin {
+			aux,
+			client_name,
+			url,
+			text: "".to_string(),
+			hints: vec![],
+			lines: vec![],
+		})
+	}
+	fn render(&mut self) -> String {
+		let mut body = format!("{} ({} hints)\n\n", self.url.path(), self.hints.len());
+		self.lines.clear();
+		// Character length of body[..counted], updated as hint lines are added.
+		let (mut counted, mut nchars) = (0, 0);
+		let mut hints = self.hints.iter().enumerate().peekable();
+		for (n, line) in self.text.lines().enumerate() {
+			writeln!(&mut body, "{}", line).unwrap();
+			while let Some((i, hint)) = hints.next_if(|(_, h)| h.position.line as usize <= n) {
+				nchars += body[counted..].chars().count();
+				counted = body.len();
+				let start = nchars;
+				let kind = match hint.kind {
+					Some(InlayHintKind::TYPE) => "type",
+					Some(InlayHintKind::PARAMETER) => "parameter",
+					_ => "hint",
+				};
+				let loc = Location::new(self.url.clone(), Range::new(hint.position, hint.position));
+				write!(
+					&mut body,
+					"\t{} {}: {}",
+					location_to_plumb(&loc, &self.text),
+					kind,
+					inlay_hint_label(&hint.label)
+				)
+				.unwrap();
+				nchars += body[counted..].chars().count();
+				counted = body.len();
+				self.lines.push((start, nchars, i));
+				body.push('\n');
+				for tooltip in inlay_hint_tooltips(hint) {
+					for line in tooltip.trim().lines() {
+						writeln!(&mut body, "\t\t{}", line).unwrap();
+					}
+				}
+			}
+		}
+		body
+	}
+	/// Returns the index of the hint on the body line containing q0.
+	fn hint_at(&self, q0: usize) -> Option<usize> {
+		self.lines
+			.iter()
+			.find(|(start, end, _)| *start <= q0 && q0 <= *end)
+			.map(|(_, _, i)| *i)
+	}
+}
+
+/// The +Folds window: an outline of a file's folding ranges.
+struct FoldsWin {
+	aux: AuxWin,
+	client_name: String,
+	url: Url,
+	/// file contents when the ranges were requested.
+	text: String,
+	/// (range, nesting depth) sorted by position, with parents before children.
+	folds: Vec<(FoldingRange, usize)>,
+	/// indexes of folds whose children are hidden.
+	collapsed: HashSet<usize>,
+	/// (start, address end, end, fold index) character offsets of each entry in
+	/// the body.
+	entries: Vec<(usize, usize, usize, usize)>,
+}
+
+impl FoldsWin {
+	fn new(client_name: String, url: Url, aux_s: Sender<(usize, Event)>) -> Result<FoldsWin> {
+		let aux = AuxWin::new("+Folds", " Get Next Prev ", &["Next", "Prev"], aux_s)?;
+		Ok(FoldsWin {
+			aux,
+			client_name,
+			url,
+			text: "".to_string(),
+			folds: vec![],
+			collapsed: HashSet::new(),
+			entries: vec![],
+		})
+	}
+	fn set_folds(&mut self, mut ranges: Vec<FoldingRange>) {
+		ranges.sort_by(|a, b| {
+			a.start_line
+				.cmp(&b.start_line)
+				.then(b.end_line.cmp(&a.end_line))
+		});
+		// Stack of end lines of the folds enclosing the current one.
+		let mut parents: Vec<u32> = vec![];
+		self.folds = ranges
+			.into_iter()
+			.map(|r| {
+				while matches!(parents.last(), Some(&end) if end < r.end_line) {
+					parents.pop();
+				}
+				let depth = parents.len();
+				parents.push(r.end_line);
+				(r, depth)
+			})
+			.collect();
+		self.collapsed.clear();
+	}
+	fn render(&mut self) -> String {
+		let mut body = format!("{} ({} folds)\n\n", self.url.path(), self.folds.len());
+		let mut nchars = body.chars().count();
+		self.entries.clear();
+		let lines: Vec<&str> = self.text.lines().collect();
+		// Depth of the collapsed fold whose children are being skipped.
+		let mut hidden_below: Option<usize> = None;
+		for (i, (r, depth)) in self.folds.iter().enumerate() {
+			match hidden_below {
+				Some(d) if *depth > d => continue,
+				_ => hidden_below = None,
+			}
+			let has_children = matches!(self.folds.get(i + 1), Some((_, d)) if d > depth);
+			let marker = if !has_children {
+				' '
+			} else if self.collapsed.contains(&i) {
+				hidden_below = Some(*depth);
+				'+'
+			} else {
+				'-'
+			};
+			let start = Position::new(r.start_line, r.start_character.unwrap_or(0));
+			let addr = location_to_plumb(
+				&Location::new(self.url.clone(), Range::new(start, start)),
+				&self.text,
+			);
+			let prefix = format!("{}{} ", "\t".repeat(*depth), marker);
+			let mut entry = format!(
+				"{}{} {} ({} lines",
+				prefix,
+				addr,
+				lines.get(r.start_line as usize).unwrap_or(&"").trim(),
+				r.end_line - r.start_line + 1,
+			);
+			if let Some(kind) = &r.kind {
+				write!(&mut entry, ", {}", format!("{:?}", kind).to_lowercase()).unwrap();
+			}
+			entry.push(')');
+			let len = entry.chars().count();
+			let addr_end = nchars + prefix.chars().count() + addr.chars().count();
+			self.entries.push((nchars, addr_end, nchars + len, i));
+			nchars += len + 1;
+			body.push_str(&entry);
+			body.push('\n');
+		}
+		body
+	}
+	/// Handles a look at q0: returns the address of the entry if q0 is on it,
+	/// otherwise toggles whether the entry's children are shown.
+	fn look(&mut self, q0: usize) -> Option<String> {
+		let (start, addr_end, _, i) = *self
+			.entries
+			.iter()
+			.find(|(start, _, end, _)| *start <= q0 && q0 <= *end)?;
+		let prefix = self.folds[i].1 + 2;
+		if q0 >= start + prefix && q0 <= addr_end {
+			let r = &self.folds[i].0;
+			let start = Position::new(r.start_line, r.start_character.unwrap_or(0));
+			return Some(location_to_plumb(
+				&Location::new(self.url.clone(), Range::new(start, start)),
+				&self.text,
+			));
+		}
+		if !self.collapsed.remove(&i) {
+			self.collapsed.insert(i);
+		}
+		None
+	}
+	/// Returns the start line of the next (or previous) fold at the same depth as
+	/// the innermost fold containing line.
+	fn sibling(&self, line: u32, next: bool) -> Option<u32> {
+		let current = self
+			.folds
+			.iter()
+			.filter(|(r, _)| r.start_line <= line && line <= r.end_line)
+			.max_by_key(|(_, depth)| *depth);
+		let (depth, from) = match current {
+			Some((r, depth)) => (*depth, r.start_line),
+			None => (0, line),
+		};
+		let mut same = self.folds.iter().filter(|(_, d)| *d == depth);
+		let found = if next {
+			same.find(|(r, _)| r.start_line > line)
+		} else {
+			same.rev().find(|(r, _)| r.start_line < from)
+		};
+		found.map(|(r, _)| r.start_line)
+	}
+}
+
+/// A +Outline window: the nested document symbols of a file.
+struct OutlineWin {
+	aux: AuxWin,
+	client_name: String,
+	url: Url,
+	/// file contents when the symbols were requested.
+	text: String,
+	symbols: Vec<DocumentSymbol>,
+	/// if set, only show symbols of these kinds (lowercase names).
+	kinds: Option<Vec<String>>,
+	/// if set, only show symbols whose name matches.
+	name: Option<Regex>,
+	/// error from the last Name command.
+	error: Option<String>,
+}
+
+impl OutlineWin {
+	fn new(client_name: String, url: Url, aux_s: Sender<(usize, Event)>) -> Result<OutlineWin> {
+		let aux = AuxWin::new(
+			&format!("{}+Outline", url.path()),
+			" Get All Kind Name ",
+			&["All", "Kind", "Name"],
+			aux_s,
+		)?;
+		Ok(OutlineWin {
+			aux,
+			client_name,
+			url,
+			text: "".to_string(),
+			symbols: vec![],
+			kinds: None,
+			name: None,
+			error: None,
+		})
+	}
+	/// Runs a tag command. Kind takes a comma-separated list of symbol kinds and
+	/// Name a regex, either following the command or from a chorded argument.
+	fn run_cmd(&mut self, ev: &Event) -> Result<()> {
+		let cmd = ev.text.split_whitespace().next().unwrap_or("");
+		let arg = cmd_arg(ev);
+		self.error = None;
+		match cmd {
+			"All" => {
+				self.kinds = None;
+				self.name = None;
+			}
+			"Kind" => {
+				self.kinds = if arg.is_empty() {
+					None
+				} else {
+					Some(
+						arg.split(|c: char| c == ',' || c.is_whitespace())
+							.filter(|k| !k.is_empty())
+							.map(|k| k.to_lowercase())
+							.collect(),
+					)
+				};
+			}
+			"Name" => self.error = set_filter(&mut self.name, &arg),
+			_ => {}
+		}
+		Ok(())
+	}
+	/// Sets the symbols from a response. Flat responses are shown unnested.
+	fn set_symbols(&mut self, msg: DocumentSymbolResponse) {
+		self.symbols = match msg {
+			DocumentSymbolResponse::Nested(dss) => dss,
+			#[allow(deprecated)]
+			DocumentSymbolResponse::Flat(sis) => sis
+				.into_iter()
+				.map(|si| DocumentSymbol {
+					name: match si.container_name {
+						Some(c) if !c.is_empty() => format!("{}::{}", c, si.name),
+						_ => si.name,
+					},
+					detail: None,
+					kind: si.kind,
+					tags: si.tags,
+					deprecated: si.deprecated,
+					range: si.location.range,
+					selection_range: si.location.range,
+					children: None,
+				})
+				.collect(),
+		};
+		fn sort(dss: &mut Vec<DocumentSymbol>) {
+			dss.sort_by_key(|ds| ds.range.start);
+			for ds in dss {
+				if let Some(children) = ds.children.as_mut() {
+					sort(children);
+				}
+			}
+		}
+		sort(&mut self.symbols);
+	}
+	fn render(&self) -> String {
+		let mut filters = vec![];
+		if let Some(kinds) = &self.kinds {
+			filters.push(format!("kind in {}", kinds.join(",")));
+		}
+		if let Some(name) = &self.name {
+			filters.push(format!("name ~ {}", name));
+		}
+		let mut body = String::new();
+		let n = self.render_symbols(&mut body, &self.symbols, 0);
+		let filters = if filters.is_empty() {
+			"".to_string()
+		} else {
+			format!(", {}", filters.join(", "))
+		};
+		format!(
+			"{}{} ({} symbols{})\n\n{}",
+			error_header(&self.error),
+			self.url.path(),
+			n,
+			filters,
+			body
+		)
+	}
+	/// Writes the symbols in dss that match the filters, or have descendants
+	/// that do, and returns the number of matches.
+	fn render_symbols(&self, body: &mut String, dss: &[DocumentSymbol], depth: usize) -> usize {
+		let mut n = 0;
+		for ds in dss {
+			let mut children = String::new();
+			let nchildren = match &ds.children {
+				Some(c) => self.render_symbols(&mut children, c, depth + 1),
+				None => 0,
+			};
+			let matches = self.matches(ds);
+			if !matches && nchildren == 0 {
+				continue;
+			}
+			if matches {
+				n += 1;
+			}
+			n += nchildren;
+			write!(
+				body,
+				"{}{} {}",
+				"\t".repeat(depth),
+				symbol_icon(ds.kind),
+				ds.name
+			)
+			.unwrap();
+			if let Some(detail) = &ds.detail {
+				write!(body, " {}", detail.lines().next().unwrap_or("")).unwrap();
+			}
+			writeln!(
+				body,
+				" {}",
+				location_to_plumb(
+					&Location::new(self.url.clone(), ds.selection_range),
+					&self.text
+				)
+			)
+			.unwrap();
+			body.push_str(&children);
+		}
+		n
+	}
+	fn matches(&self, ds: &DocumentSymbol) -> bool {
+		if let Some(kinds) = &self.kinds {
+			if !kinds.contains(&format!("{:?}", ds.kind).to_lowercase()) {
+				return false;
+			}
+		}
+		if let Some(name) = &self.name {
+			if !name.is_match(&ds.name) {
+				return false;
+			}
+		}
+		true
+	}
+}
+
+impl DiagWin {
+	fn new(aux_s: Sender<(usize, Event)>) -> Result<DiagWin> {
+		let aux = AuxWin::new(
+			"+Diagnostics",
+			" Get All Error Warning Info Hint File ",
+			&["All", "Error", "Warning", "Info", "Hint", "File"],
+			aux_s,
+		)?;
+		Ok(DiagWin {
+			aux,
+			severity: DiagnosticSeverity::HINT,
+			file: None,
+			error: None,
+		})
+	}
+	/// Runs a tag command. File takes a regex argument, either following it or
+	/// from a chorded argument.
+	fn run_cmd(&mut self, ev: &Event) -> Result<()> {
+		let cmd = ev.text.split_whitespace().next().unwrap_or("");
+		self.error = None;
+		match cmd {
+			"All" => {
+				self.severity = DiagnosticSeverity::HINT;
+				self.file = None;
+			}
+			"Error" => self.severity = DiagnosticSeverity::ERROR,
+			"Warning" => self.severity = DiagnosticSeverity::WARNING,
+			"Info" => self.severity = DiagnosticSeverity::INFORMATION,
+			"Hint" => self.severity = DiagnosticSeverity::HINT,
+			"File" => self.error = set_filter(&mut self.file, &cmd_arg(ev)),
+			_ => {}
+		}
+		// Force a rewrite so Get always redraws.
+		self.aux.body.clear();
+		Ok(())
+	}
+	/// Renders diags, where locs holds the plumbable locations of each.
+	fn render(
+		&self,
+		diags: &BTreeMap<Url, Vec<Diagnostic>>,
+		locs: &HashMap<Url, Vec<(String, Vec<String>)>>,
+	) -> String {
+		let mut body = String::new();
+		let mut filters = vec![format!("severity <= {:?}", self.severity)];
+		if let Some(file) = &self.file {
+			filters.push(format!("file ~ {}", file));
+		}
+		let mut n = 0;
+		for (url, ds) in diags {
+			let path = url.path();
+			if let Some(file) = &self.file {
+				if !file.is_match(path) {
+					continue;
+				}
+			}
+			for (d, (loc, related_locs)) in ds.iter().zip(&locs[url]) {
+				let severity = d.severity.unwrap_or(DiagnosticSeverity::ERROR);
+				if severity > self.severity {
+					continue;
+				}
+				n += 1;
+				write!(&mut body, "{}: {:?}", loc, severity,).unwrap();
+				if let Some(source) = &d.source {
+					write!(&mut body, " {}", source).unwrap();
+				}
+				match &d.code {
+					Some(NumberOrString::Number(code)) => write!(&mut body, "[{}]", code).unwrap(),
+					Some(NumberOrString::String(code)) => write!(&mut body, "[{}]", code).unwrap(),
+					None => {}
+				}
+				let mut lines = d.message.lines();
+				writeln!(&mut body, ": {}", lines.next().unwrap_or("")).unwrap();
+				for line in lines {
+					writeln!(&mut body, "\t{}", line).unwrap();
+				}
+				if let Some(desc) = &d.code_description {
+					writeln!(&mut body, "\t{}", desc.href).unwrap();
+				}
+				for (related, related_loc) in
+					d.related_information.iter().flatten().zip(related_locs)
+				{
+					writeln!(
+						&mut body,
+						"\t{}: {}",
+						related_loc,
+						related.message.lines().next().unwrap_or(""),
+					)
+					.unwrap();
+				}
+			}
+		}
+		format!(
+			"{}{} diagnostics ({})\n\n{}",
+			error_header(&self.error),
+			n,
+			filters.join(", "),
+			body
+		)
+	}
 }
 
 impl Server {
@@ -289,6 +1265,7 @@ impl Server {
 		let (log_s, log_r) = bounded(0);
 		let (ev_s, ev_r) = bounded(0);
 		let (err_s, err_r) = bounded(0);
+		let (aux_s, aux_r) = bounded(0);
 		let mut w = Win::new()?;
 		w.name("acre")?;
 		let mut wev = w.events()?;
@@ -306,18 +1283,45 @@ impl Server {
 			output: "".to_string(),
 			body: "".to_string(),
 			focus: "".to_string(),
+			file_focus: None,
+			nav_back: vec![],
+			nav_forward: vec![],
 			progress: HashMap::new(),
 			requests,
 			diags: BTreeMap::new(),
+			diag_locs: HashMap::new(),
+			diag_result_ids: HashMap::new(),
 			current_hover: None,
+			diag_win: None,
+			hints_win: None,
+			folds_win: None,
+			outline_wins: vec![],
+			outline_requests: HashSet::new(),
+			saves: HashMap::new(),
+			presaved: HashSet::new(),
+			partials: HashMap::new(),
+			next_partial: 0,
+			partial_lines: HashMap::new(),
+			message_requests: vec![],
+			message_addrs: vec![],
+			snippet: None,
+			highlights: None,
+			selections: HashMap::new(),
+			selection_requests: HashMap::new(),
+			signature: None,
 			log_r,
 			ev_r,
 			err_r,
+			aux_s,
+			aux_r,
 			clients: cls,
 			capabilities: HashMap::new(),
 			files: HashMap::new(),
 			config,
 			autorun: HashMap::new(),
+			peeks: HashMap::new(),
+			docs: HashMap::new(),
+			doc_wins: vec![],
 		};
 		let err_s1 = err_s.clone();
 		thread::Builder::new()
@@ -359,7 +1363,10 @@ impl Server {
 						"Del" => {
 							return;
 						}
-						"Get" => {
+						"Get" | "Diagnostics" | "back" | "forward" => {
+							ev_s.send(ev).unwrap();
+						}
+						text if text.split_whitespace().next() == Some("Symbols") => {
 							ev_s.send(ev).unwrap();
 						}
 						_ => {
@@ -379,12 +1386,27 @@ impl Server {
 	/// Runs f if self.current_hover is Some and matches the Url, and updates the hover action addrs
 	/// and body.
 	fn set_hover<F: FnOnce(&mut WindowHover)>(&mut self, url: &Url, f: F) {
+		let limits = self.config.hover.clone().unwrap_or_default();
 		if let Some(hover) = self.current_hover.as_mut() {
 			if &hover.url == url {
 				f(hover);
 
 				hover.actions.clear();
-				hover.actions.extend(hover.code_actions.clone());
+				let filter = hover.action_filter.clone();
+				let code_actions = hover
+					.code_actions
+					.iter()
+					.filter(|a| match (&filter, action_kind(a)) {
+						(None, _) => true,
+						(Some(filter), Some(kind)) => kind_matches(kind, filter),
+						(Some(_), None) => false,
+					})
+					.cloned()
+					.collect();
+				let mut listed: Vec<Action> = group_code_actions(code_actions)
+					.into_iter()
+					.map(Action::Command)
+					.collect();
 				if let Some(token) = &hover.token {
 					let mut v = vec![];
 					for a in &hover.completion {
@@ -395,25 +1417,44 @@ impl Server {
 						};
 						if filter.contains(token) {
 							v.push(Action::Completion(a.clone()));
-							if v.len() == 10 {
+							if v.len() == limits.completions.unwrap_or(10) {
 								break;
 							}
 						}
 					}
-					hover.actions.extend(v);
+					listed.extend(v);
 				}
 
 				// Until lenses work, forcibly clear them.
 				hover.lens.clear();
-				hover
-					.actions
-					.extend(hover.lens.iter().map(|lens| Action::CodeLens(lens.clone())));
+				listed.extend(hover.lens.iter().map(|lens| Action::CodeLens(lens.clone())));
+				listed.truncate(limits.actions.unwrap_or(10));
+				if filter.is_some() {
+					hover.actions.push(Action::Filter(None));
+				}
+				// Head each kind of code action with a filter for it.
+				let mut kind = None;
+				for action in listed {
+					if let Action::Command(a) = &action {
+						let k = action_kind(a);
+						if k.is_some() && k != kind.as_ref() {
+							hover.actions.push(Action::Filter(k.cloned()));
+						}
+						kind = k.cloned();
+					}
+					hover.actions.push(action);
+				}
+				hover
+					.actions
+					.extend(hover.links.iter().map(|link| Action::Link(link.clone())));
 
 				hover.body.clear();
 
 				hover.action_addrs.clear();
-				for (idx, action) in hover.actions.iter().take(10).enumerate() {
-					hover.action_addrs.push((hover.body.len(), Some(idx)));
+				for (idx, action) in hover.actions.iter().enumerate() {
+					hover
+						.action_addrs
+						.push((hover.body.chars().count(), Some(idx)));
 					let newline = if hover.body.is_empty() { "" } else { "\n" };
 					match action {
 						Action::Command(CodeActionOrCommand::Command(cmd)) => {
@@ -421,6 +1462,19 @@ impl Server {
 						}
 						Action::Command(CodeActionOrCommand::CodeAction(action)) => {
 							write!(&mut hover.body, "{}[{}]", newline, action.title).unwrap();
+							if action.is_preferred.unwrap_or(false) {
+								write!(&mut hover.body, " (preferred)").unwrap();
+							}
+							if let Some(disabled) = &action.disabled {
+								write!(&mut hover.body, " disabled: {}", disabled.reason).unwrap();
+							}
+						}
+						Action::Filter(Some(kind)) => {
+							write!(&mut hover.body, "{}[actions {}]", newline, kind.as_str())
+								.unwrap();
+						}
+						Action::Filter(None) => {
+							write!(&mut hover.body, "{}[actions]", newline).unwrap();
 						}
 						Action::Completion(item) => {
 							write!(&mut hover.body, "{}[insert] {}:", newline, item.label).unwrap();
@@ -448,9 +1502,20 @@ impl Server {
 							)
 							.unwrap();
 						}
+						Action::Link(link) => {
+							write!(&mut hover.body, "{}[open]", newline).unwrap();
+							if let Some(target) = &link.target {
+								write!(&mut hover.body, " {}", target).unwrap();
+							}
+							if let Some(tooltip) = &link.tooltip {
+								write!(&mut hover.body, " {}", tooltip).unwrap();
+							}
+							write!(&mut hover.body, " (line {})", link.range.start.line + 1)
+								.unwrap();
+						}
 					}
 				}
-				hover.action_addrs.push((hover.body.len(), None));
+				hover.action_addrs.push((hover.body.chars().count(), None));
 				if !hover.body.is_empty() {
 					hover.body.push_str("\n");
 				}
@@ -459,7 +1524,7 @@ impl Server {
 					if !hover.body.is_empty() {
 						hover.body.push_str("\n");
 					}
-					for line in text.trim().lines().take(10) {
+					for line in text.trim().lines().take(limits.lines.unwrap_or(10)) {
 						hover.body.push_str(line);
 						hover.body.push_str("\n");
 					}
@@ -468,12 +1533,529 @@ impl Server {
 					if !hover.body.is_empty() {
 						hover.body.push_str("\n");
 					}
-					for line in text.trim().lines().take(10) {
+					for line in text.trim().lines().take(limits.lines.unwrap_or(10)) {
 						hover.body.push_str(line);
 						hover.body.push_str("\n");
 					}
 				}
+				if let Some(text) = &hover.peek {
+					if !hover.body.is_empty() {
+						hover.body.push('\n');
+					}
+					hover.body.push_str(text);
+				}
+			}
+		}
+	}
+	fn set_diagnostics(&mut self, url: Url, diagnostics: Vec<Diagnostic>) {
+		if diagnostics.is_empty() {
+			self.diags.remove(&url);
+			self.diag_locs.remove(&url);
+			return;
+		}
+		let text = self.file_text(&url);
+		let mut locs = vec![];
+		for d in &diagnostics {
+			let mut related = vec![];
+			for r in d.related_information.iter().flatten() {
+				let related_text = if r.location.uri == url {
+					text.clone()
+				} else {
+					self.file_text(&r.location.uri)
+				};
+				related.push(location_to_plumb(&r.location, &related_text));
+			}
+			locs.push((
+				location_to_plumb(&Location::new(url.clone(), d.range), &text),
+				related,
+			));
+		}
+		self.diag_locs.insert(url.clone(), locs);
+		self.diags.insert(url, diagnostics);
+	}
+	/// Stores a pulled diagnostic report for url. Unchanged reports only update
+	/// the result id.
+	fn set_pulled_diagnostics(
+		&mut self,
+		client_name: &str,
+		url: Url,
+		result_id: Option<String>,
+		diagnostics: Option<Vec<Diagnostic>>,
+	) {
+		let ids = self
+			.diag_result_ids
+			.entry(client_name.to_string())
+			.or_default();
+		match result_id {
+			Some(id) => ids.insert(url.clone(), id),
+			None => ids.remove(&url),
+		};
+		if let Some(diagnostics) = diagnostics {
+			self.set_diagnostics(url, diagnostics);
+		}
+	}
+	/// Requests pull diagnostics for url if the client supports them.
+	fn pull_diagnostics(&mut self, client_name: &str, url: Url) -> Result<()> {
+		let identifier = match self
+			.capabilities
+			.get(client_name)
+			.and_then(diagnostic_options)
+		{
+			Some(opts) => opts.identifier.clone(),
+			None => return Ok(()),
+		};
+		let previous_result_id = self
+			.diag_result_ids
+			.get(client_name)
+			.and_then(|ids| ids.get(&url))
+			.cloned();
+		self.send_request::<DocumentDiagnosticRequest>(
+			client_name,
+			url.clone(),
+			DocumentDiagnosticParams {
+				text_document: TextDocumentIdentifier::new(url),
+				identifier,
+				previous_result_id,
+				work_done_progress_params,
+				partial_result_params,
+			},
+		)?;
+		Ok(())
+	}
+	/// Requests workspace diagnostics from all clients that support them and
+	/// don't already have a request outstanding.
+	fn pull_workspace_diagnostics(&mut self) -> Result<()> {
+		let mut pull = vec![];
+		for (client_name, caps) in &self.capabilities {
+			let opts = match diagnostic_options(caps) {
+				Some(opts) if opts.workspace_diagnostics => opts,
+				_ => continue,
+			};
+			if self.requests.iter().any(|(id, (method, _))| {
+				&id.client_name == client_name && method == WorkspaceDiagnosticRequest::METHOD
+			}) {
+				continue;
+			}
+			let previous_result_ids = self
+				.diag_result_ids
+				.get(client_name)
+				.map(|ids| {
+					ids.iter()
+						.map(|(uri, value)| PreviousResultId {
+							uri: uri.clone(),
+							value: value.clone(),
+						})
+						.collect()
+				})
+				.unwrap_or_default();
+			pull.push((
+				client_name.clone(),
+				WorkspaceDiagnosticParams {
+					identifier: opts.identifier.clone(),
+					previous_result_ids,
+					work_done_progress_params,
+					partial_result_params,
+				},
+			));
+		}
+		for (client_name, params) in pull {
+			self.send_request::<WorkspaceDiagnosticRequest>(
+				&client_name,
+				Url::parse("file:///").unwrap(),
+				params,
+			)?;
+		}
+		Ok(())
+	}
+	/// Sends request R with a partial result token so its results are streamed
+	/// into the output as they arrive. Requests in the same group (or a new one
+	/// for None, which is returned) are shown together.
+	fn send_partial_request<R: Request>(
+		&mut self,
+		client_name: &str,
+		url: Url,
+		group: Option<usize>,
+		make_params: impl FnOnce(PartialResultParams) -> R::Params,
+	) -> Result<usize> {
+		self.next_partial += 1;
+		let group = match group {
+			Some(group) => group,
+			None => {
+				// Forget the lines of finished searches.
+				let live: HashSet<usize> = self.partials.values().map(|p| p.group).collect();
+				self.partial_lines.retain(|group, _| live.contains(group));
+				self.next_partial
+			}
+		};
+		let token = format!("acre-partial-{}", self.next_partial);
+		let params = make_params(PartialResultParams {
+			partial_result_token: Some(NumberOrString::String(token.clone())),
+		});
+		let id = self.send_request::<R>(client_name, url.clone(), params)?;
+		self.partials.insert(
+			token,
+			PartialResult {
+				client_id: ClientId::new(client_name, id),
+				method: R::METHOD.to_string(),
+				url,
+				group,
+				locations: vec![],
+				texts: HashMap::new(),
+			},
+		);
+		Ok(group)
+	}
+	/// Adds symbol output lines to those of group and shows them all.
+	fn show_partial_lines(&mut self, group: usize, lines: Vec<String>) {
+		let all = self.partial_lines.entry(group).or_default();
+		all.extend(lines);
+		if !all.is_empty() {
+			self.output = all.join("\n");
+		}
+	}
+	/// Removes and returns the results streamed for a request.
+	fn take_partial(&mut self, client_id: &ClientId) -> Option<PartialResult> {
+		let token = self
+			.partials
+			.iter()
+			.find(|(_, p)| &p.client_id == client_id)
+			.map(|(token, _)| token.clone())?;
+		self.partials.remove(&token)
+	}
+	/// Adds a batch of partial results and shows everything received so far.
+	fn partial_progress(&mut self, token: &str, value: &serde_json::value::RawValue) -> Result<()> {
+		let (method, url, group) = match self.partials.get(token) {
+			Some(p) => (p.method.clone(), p.url.clone(), p.group),
+			None => return Ok(()),
+		};
+		let lines = match method.as_str() {
+			References::METHOD => {
+				// Show batches as they arrive. They're sorted with the final response.
+				let locs = serde_json::from_str::<Vec<Location>>(value.get())?;
+				let mut texts = std::mem::take(&mut self.partials.get_mut(token).unwrap().texts);
+				let lines = self.locations_output(&locs, &mut texts);
+				let p = self.partials.get_mut(token).unwrap();
+				p.texts = texts;
+				p.locations.extend(locs);
+				lines
+			}
+			DocumentSymbolRequest::METHOD => {
+				let msg = serde_json::from_str::<DocumentSymbolResponse>(value.get())?;
+				let text = self.file_text(&url);
+				symbols_output(&url, &text, msg)
+			}
+			WorkspaceSymbolRequest::METHOD => {
+				let msg = serde_json::from_str::<WorkspaceSymbolResponse>(value.get())?;
+				workspace_symbols_output(msg, |url| self.file_text(url))
+			}
+			_ => return Ok(()),
+		};
+		self.show_partial_lines(group, lines);
+		Ok(())
+	}
+	/// Shows the symbol lines of a response along with any streamed before it.
+	fn show_symbols(&mut self, partial: Option<PartialResult>, lines: Vec<String>) {
+		match partial {
+			Some(p) => self.show_partial_lines(p.group, lines),
+			None if !lines.is_empty() => self.output = lines.join("\n"),
+			None => {}
+		}
+	}
+	/// Searches for symbols matching query in all servers that support it.
+	fn workspace_symbols(&mut self, query: &str) -> Result<()> {
+		let clients: Vec<String> = self
+			.capabilities
+			.iter()
+			.filter(|(_, caps)| match caps.workspace_symbol_provider {
+				None | Some(OneOf::Left(false)) => false,
+				Some(_) => true,
+			})
+			.map(|(name, _)| name.clone())
+			.collect();
+		let mut group = None;
+		for client_name in clients {
+			group = Some(self.send_partial_request::<WorkspaceSymbolRequest>(
+				&client_name,
+				Url::parse("file:///").unwrap(),
+				group,
+				|partial| WorkspaceSymbolParams {
+					partial_result_params: partial,
+					work_done_progress_params,
+					query: query.to_string(),
+				},
+			)?);
+		}
+		Ok(())
+	}
+	/// Returns the dot of filename's window.
+	fn nav_entry(&mut self, filename: &str) -> Option<NavEntry> {
+		let (_, sw) = self.get_sw_by_name(filename)?;
+		let dot = sw.pos().ok()?;
+		Some(NavEntry {
+			filename: filename.to_string(),
+			dot,
+		})
+	}
+	/// Saves entry so [back] can return to it.
+	fn push_nav(&mut self, entry: NavEntry) {
+		if self.nav_back.last() != Some(&entry) {
+			self.nav_back.push(entry);
+		}
+		self.nav_forward.clear();
+	}
+	/// Plumbs loc. If it was sent, saves the focused file window's dot from
+	/// before the jump.
+	fn jump(&mut self, loc: String) -> Result<()> {
+		let entry = self
+			.file_focus
+			.clone()
+			.and_then(|filename| self.nav_entry(&filename));
+		if plumb_location(loc)? {
+			if let Some(entry) = entry {
+				self.push_nav(entry);
+			}
+		}
+		Ok(())
+	}
+	/// Plumbs the previous (or, if forward, next) saved location, saving the
+	/// current one so the move can be undone.
+	fn navigate(&mut self, forward: bool) -> Result<()> {
+		let entry = if forward {
+			self.nav_forward.pop()
+		} else {
+			self.nav_back.pop()
+		};
+		let entry = match entry {
+			Some(e) => e,
+			None => return Ok(()),
+		};
+		let current = self
+			.file_focus
+			.clone()
+			.and_then(|filename| self.nav_entry(&filename));
+		if let Some(current) = current {
+			if forward {
+				self.nav_back.push(current);
+			} else {
+				self.nav_forward.push(current);
+			}
+		}
+		plumb_location(format!(
+			"{}:#{},#{}",
+			entry.filename, entry.dot.0, entry.dot.1
+		))?;
+		Ok(())
+	}
+	/// Plumbs the target of a goto response. If there are several targets, lists
+	/// them in the output instead.
+	fn goto(&mut self, goto: GotoDefinitionResponse) -> Result<()> {
+		let mut locs = match goto {
+			GotoDefinitionResponse::Scalar(loc) => vec![loc],
+			GotoDefinitionResponse::Array(locs) => locs,
+			// Use the selection range so the plumbed address is at the identifier
+			// instead of the start of the enclosing item.
+			GotoDefinitionResponse::Link(links) => links
+				.into_iter()
+				.map(|link| Location::new(link.target_uri, link.target_selection_range))
+				.collect(),
+		};
+		match locs.len() {
+			0 => Ok(()),
+			1 => {
+				let text = self.file_text(&locs[0].uri);
+				self.jump(location_to_plumb(&locs[0], &text))
+			}
+			_ => {
+				locs.sort_by(cmp_location);
+				locs.dedup();
+				self.output = self.locations_output(&locs, &mut HashMap::new()).join("\n");
+				Ok(())
+			}
+		}
+	}
+	/// Returns a plumbable line for each location followed by its indented source
+	/// line. files caches the texts of the files read.
+	fn locations_output(
+		&mut self,
+		locs: &[Location],
+		files: &mut HashMap<Url, String>,
+	) -> Vec<String> {
+		let mut o = Vec::new();
+		for x in locs {
+			let text = match files.get(&x.uri) {
+				Some(text) => text,
+				None => {
+					let text = self.file_text(&x.uri);
+					files.entry(x.uri.clone()).or_insert(text)
+				}
+			};
+			o.push(location_to_plumb(&x, text));
+			if let Some(line) = text.lines().nth(x.range.start.line.try_into().unwrap()) {
+				o.push(format!("\t{}", line.trim()));
+			}
+		}
+		o
+	}
+	/// Returns the contents of uri from its open window, or else from disk.
+	fn file_text(&mut self, uri: &Url) -> String {
+		match self.get_sw_by_url(uri) {
+			Some((_, win)) => win.body().unwrap_or_default(),
+			None => read_to_string(uri.path()).unwrap_or_default(),
+		}
+	}
+	/// Handles responses to [peek] requests. target is None for the definition
+	/// response and the definition's location for the symbol response.
+	fn peek_response(
+		&mut self,
+		client_name: &str,
+		url: Url,
+		target: Option<Location>,
+		result: &serde_json::value::RawValue,
+	) -> Result<()> {
+		let loc = match target {
+			None => {
+				let msg = serde_json::from_str::<Option<GotoDefinitionResponse>>(result.get())?;
+				let (loc, range) = match msg {
+					Some(GotoDefinitionResponse::Scalar(loc)) => (loc, None),
+					Some(GotoDefinitionResponse::Array(locs)) if !locs.is_empty() => {
+						(locs[0].clone(), None)
+					}
+					Some(GotoDefinitionResponse::Link(links)) if !links.is_empty() => {
+						let link = &links[0];
+						(
+							Location::new(link.target_uri.clone(), link.target_selection_range),
+							Some(link.target_range),
+						)
+					}
+					_ => return Ok(()),
+				};
+				if let Some(range) = range {
+					return self.show_peek(url, loc, range);
+				}
+				let symbols = self
+					.capabilities
+					.get(client_name)
+					.map(|caps| caps.document_symbol_provider.is_some())
+					.unwrap_or(false);
+				if !symbols {
+					let range = loc.range;
+					return self.show_peek(url, loc, range);
+				}
+				let id = self.send_request::<DocumentSymbolRequest>(
+					client_name,
+					url,
+					DocumentSymbolParams {
+						text_document: TextDocumentIdentifier::new(loc.uri.clone()),
+						work_done_progress_params,
+						partial_result_params,
+					},
+				)?;
+				self.peeks.insert(ClientId::new(client_name, id), Some(loc));
+				return Ok(());
+			}
+			Some(loc) => loc,
+		};
+		let msg = serde_json::from_str::<Option<DocumentSymbolResponse>>(result.get())?;
+		let pos = loc.range.start;
+		let contains = |r: &Range| r.start <= pos && pos <= r.end;
+		let mut range = loc.range;
+		match msg {
+			Some(DocumentSymbolResponse::Flat(sis)) => {
+				// Use the innermost symbol containing the definition.
+				if let Some(si) = sis
+					.iter()
+					.filter(|si| si.location.uri == loc.uri && contains(&si.location.range))
+					.max_by_key(|si| si.location.range.start)
+				{
+					range = si.location.range;
+				}
+			}
+			Some(DocumentSymbolResponse::Nested(mut dss)) => {
+				while let Some(ds) = dss.into_iter().find(|ds| contains(&ds.range)) {
+					range = ds.range;
+					dss = ds.children.unwrap_or_default();
+				}
+			}
+			None => {}
+		}
+		self.show_peek(url, loc, range)
+	}
+	/// Shows the lines of range, with loc as a header, in url's hover.
+	fn show_peek(&mut self, url: Url, loc: Location, range: Range) -> Result<()> {
+		let text = self.file_text(&loc.uri);
+		let mut end = range.end.line;
+		if range.end.character == 0 && end > range.start.line {
+			end -= 1;
+		}
+		let mut peek = format!("{}\n", location_to_plumb(&loc, &text));
+		for line in text
+			.lines()
+			.skip(range.start.line as usize)
+			.take((end - range.start.line) as usize + 1)
+		{
+			peek.push_str(line);
+			peek.push('\n');
+		}
+		self.set_hover(&url, |hover| {
+			hover.peek = Some(peek);
+		});
+		Ok(())
+	}
+	/// Opens (or reuses) a window for name showing the rendered hover contents.
+	fn show_doc(&mut self, name: &str, contents: &HoverContents) -> Result<()> {
+		let name = if name.is_empty() {
+			"+Doc".to_string()
+		} else {
+			format!("+Doc/{}", name)
+		};
+		let text = match contents {
+			HoverContents::Scalar(ms) => marked_string_markdown(ms),
+			HoverContents::Array(mss) => mss
+				.iter()
+				.map(marked_string_markdown)
+				.collect::<Vec<_>>()
+				.join("\n"),
+			HoverContents::Markup(mc) => match mc.kind {
+				MarkupKind::Markdown => mc.value.clone(),
+				MarkupKind::PlainText => format!("{}\n", mc.value.trim_end()),
+			},
+		};
+		let body = match contents {
+			HoverContents::Markup(MarkupContent {
+				kind: MarkupKind::PlainText,
+				..
+			}) => text,
+			_ => markdown::render(&text),
+		};
+		let idx = match self.doc_wins.iter_mut().position(|dw| dw.name == name) {
+			Some(idx) => idx,
+			None => {
+				self.doc_wins.push(AuxWin::with_looks(
+					&name,
+					"",
+					&[],
+					is_doc_link,
+					self.aux_s.clone(),
+				)?);
+				self.doc_wins.len() - 1
 			}
+		};
+		let dw = &mut self.doc_wins[idx];
+		dw.set_body(&body)?;
+		dw.w.write(File::Addr, "#0")?;
+		dw.w.ctl("dot=addr")?;
+		dw.w.ctl("show")?;
+		Ok(())
+	}
+	/// Returns the diagnostics for url that overlap range.
+	fn diagnostics_in_range(&self, url: &Url, range: &Range) -> Vec<Diagnostic> {
+		match self.diags.get(url) {
+			Some(ds) => ds
+				.iter()
+				.filter(|d| range_overlaps(&d.range, range))
+				.cloned()
+				.collect(),
+			None => vec![],
 		}
 	}
 	/// Returns the most recently focused win id for a filename.
@@ -500,7 +2082,8 @@ impl Server {
 		self.addr.clear();
 		// Loop through by sorted file name.
 		for file_name in &self.names {
-			self.addr.push((body.len(), Some(file_name.to_string())));
+			self.addr
+				.push((body.chars().count(), Some(file_name.to_string())));
 			write!(
 				&mut body,
 				"{}{}\n\t",
@@ -513,7 +2096,14 @@ impl Server {
 				None => continue,
 			};
 			if caps.definition_provider.is_some() {
-				body.push_str("[definition] ");
+				body.push_str("[definition] [peek] ");
+			}
+			match caps.declaration_provider {
+				None | Some(DeclarationCapability::Simple(false)) => {}
+				Some(_) => body.push_str("[declaration] "),
+			}
+			if caps.hover_provider.is_some() {
+				body.push_str("[doc] ");
 			}
 			if caps.implementation_provider.is_some() {
 				body.push_str("[impl] ");
@@ -522,15 +2112,50 @@ impl Server {
 				body.push_str("[references] ");
 			}
 			if caps.document_symbol_provider.is_some() {
-				body.push_str("[symbols] ");
+				body.push_str("[symbols] [outline] ");
 			}
 			if caps.type_definition_provider.is_some() {
 				body.push_str("[typedef] ");
 			}
+			if caps.document_highlight_provider.is_some() {
+				body.push_str("[highlight] ");
+			}
+			if caps.inlay_hint_provider.is_some() {
+				body.push_str("[hints] ");
+			}
+			if caps.folding_range_provider.is_some() {
+				body.push_str("[folds] ");
+			}
+			if caps.selection_range_provider.is_some() {
+				body.push_str("[expand] [shrink] ");
+			}
+			let snippet = self.snippet.as_ref().map(|s| &s.url);
+			let highlights = self.highlights.as_ref().map(|h| &h.url);
+			if snippet
+				.into_iter()
+				.chain(highlights)
+				.any(|url| url.path() == file_name)
+			{
+				body.push_str("[next] [prev] ");
+			}
 			body.push('\n');
 		}
-		self.addr.push((body.len(), None));
+		self.addr.push((body.chars().count(), None));
 		write!(&mut body, "-----\n")?;
+		self.message_addrs.clear();
+		for (i, req) in self.message_requests.iter().enumerate() {
+			write!(
+				&mut body,
+				"\n{}: [{:?}] {}\n",
+				req.client_name, req.params.typ, req.params.message
+			)?;
+			for (j, action) in req.params.actions.iter().flatten().enumerate() {
+				let start = body.chars().count();
+				write!(&mut body, "[{}]", action.title)?;
+				self.message_addrs.push((start, body.chars().count(), i, j));
+				body.push('\n');
+			}
+		}
 		if !self.output.is_empty() {
 			// Only take the first 50 lines.
 			let output = self
@@ -562,18 +2187,42 @@ impl Server {
 		}
 		if !self.diags.is_empty() {
 			write!(&mut body, "-----\n")?;
-			for (_, ds) in self.diags.iter().take(5) {
-				for d in ds.iter().take(3) {
-					write!(&mut body, "{}\n", d)?;
+			for (url, ds) in self.diags.iter().take(5) {
+				let locs = &self.diag_locs[url];
+				for (d, (loc, _)) in ds.iter().zip(locs).take(3) {
+					write!(
+						&mut body,
+						"{}: [{:?}] {}\n",
+						loc,
+						d.severity.unwrap_or(lsp_types::DiagnosticSeverity::ERROR),
+						d.message.lines().next().unwrap_or(""),
+					)?;
 				}
 			}
 		}
+		if let Some(dw) = self.diag_win.as_mut() {
+			let body = dw.render(&self.diags, &self.diag_locs);
+			dw.aux.set_body(&body)?;
+		}
+		if let Some(hw) = self.hints_win.as_mut() {
+			let body = hw.render();
+			hw.aux.set_body(&body)?;
+		}
+		if let Some(fw) = self.folds_win.as_mut() {
+			let body = fw.render();
+			fw.aux.set_body(&body)?;
+		}
+		for ow in self.outline_wins.iter_mut() {
+			let body = ow.render();
+			ow.aux.set_body(&body)?;
+		}
 		if self.body != body {
 			self.body = body.clone();
 			self.w.write(File::Addr, &format!(","))?;
 			self.w.write(File::Data, &body)?;
 			self.w.ctl("cleartag\nclean")?;
-			self.w.write(File::Tag, " Get")?;
+			self.w
+				.write(File::Tag, " Get Diagnostics Symbols [back] [forward]")?;
 		}
 		Ok(())
 	}
@@ -589,7 +2238,22 @@ impl Server {
 		let ids = self.ws.get_mut(&filename).unwrap();
 		let mut fsys = FSYS.lock().unwrap();
 		let ctl = fsys.open(format!("{}/ctl", winid).as_str(), OpenMode::RDWR)?;
-		let w = Win::open(&mut fsys, winid, ctl)?;
+		let mut w = Win::open(&mut fsys, winid, ctl)?;
+		// Intercept Put so edits from the server can be applied before the file
+		// is written. Otherwise, or if another program has the event file, puts
+		// are only seen in the log after the write.
+		let intercept = self
+			.config
+			.servers
+			.get(&client_name)
+			.unwrap()
+			.format_before_put
+			.unwrap_or(false);
+		if intercept && !ids.contains_key(&winid) {
+			if let Ok(wev) = w.events() {
+				intercept_put(winid, wev, self.aux_s.clone());
+			}
+		}
 		let sw = ServerWin::new(filename, w, client_name)?;
 		ids.insert(winid, sw);
 		Ok(need_open)
@@ -674,7 +2338,7 @@ impl Server {
 				msg.error.unwrap(),
 			)
 		} else if msg.id.is_some() && msg.method.is_some() {
-			self.lsp_request(msg)
+			self.lsp_request(client_name, msg)
 		} else if msg.id.is_some() {
 			self.lsp_response(ClientId::new(client_name, msg.id.unwrap()), msg, &orig_msg)
 		} else if msg.method.is_some() {
@@ -688,7 +2352,16 @@ impl Server {
 	}
 	fn lsp_error(&mut self, client_id: ClientId, err: lsp::ResponseError) -> Result<()> {
 		self.requests.remove(&client_id);
+		self.peeks.remove(&client_id);
+		self.docs.remove(&client_id);
+		self.selection_requests.remove(&client_id);
+		self.outline_requests.remove(&client_id);
+		self.take_partial(&client_id);
 		self.output = format!("lsp error: {}", err.message);
+		// Still write the file if the server can't provide its edits.
+		if let Some(save) = self.saves.remove(&client_id) {
+			return self.save_step(save.filename, save.wid, save.next);
+		}
 		Ok(())
 	}
 	fn lsp_response(
@@ -697,10 +2370,19 @@ impl Server {
 		msg: lsp::DeMessage,
 		_orig_msg: &[u8],
 	) -> Result<()> {
-		let (typ, url) = self
-			.requests
-			.remove(&client_id)
-			.expect(&format!("expected client id {:?}", client_id));
+		let (typ, url) = match self.requests.remove(&client_id) {
+			Some(r) => r,
+			// A save whose response timed out.
+			None => return Ok(()),
+		};
+		let peek = self.peeks.remove(&client_id);
+		let doc = self.docs.remove(&client_id);
+		let selection = self.selection_requests.remove(&client_id);
+		let outline = self.outline_requests.remove(&client_id);
+		let partial = self.take_partial(&client_id);
+		if let Some(save) = self.saves.remove(&client_id) {
+			return self.save_response(&client_id, url, save, msg.result);
+		}
 		let result = match msg.result {
 			Some(v) => v,
 			None => {
@@ -708,6 +2390,28 @@ impl Server {
 				return Ok(());
 			}
 		};
+		if let Some(target) = peek {
+			return self.peek_response(&client_id.client_name, url, target, &result);
+		}
+		if outline {
+			let msg = serde_json::from_str::<Option<DocumentSymbolResponse>>(result.get())?;
+			if let (Some(msg), Some(ow)) =
+				(msg, self.outline_wins.iter_mut().find(|ow| ow.url == url))
+			{
+				ow.set_symbols(msg);
+			}
+			return Ok(());
+		}
+		if let Some(selection) = selection {
+			return self.selection_response(selection, &result);
+		}
+		if let Some(name) = doc {
+			let msg = serde_json::from_str::<Option<Hover>>(result.get())?;
+			return match msg {
+				Some(msg) => self.show_doc(&name, &msg.contents),
+				None => Ok(()),
+			};
+		}
 		match typ.as_str() {
 			Initialize::METHOD => {
 				let msg = serde_json::from_str::<InitializeResult>(result.get())?;
@@ -722,7 +2426,7 @@ impl Server {
 			GotoDefinition::METHOD => {
 				let msg = serde_json::from_str::<Option<GotoDefinitionResponse>>(result.get())?;
 				if let Some(msg) = msg {
-					goto_definition(&msg)?;
+					self.goto(msg)?;
 				}
 			}
 			HoverRequest::METHOD => {
@@ -752,116 +2456,112 @@ impl Server {
 			}
 			References::METHOD => {
 				let msg = serde_json::from_str::<Option<Vec<Location>>>(result.get())?;
+				let (mut locs, mut texts) =
+					partial.map(|p| (p.locations, p.texts)).unwrap_or_default();
+				locs.extend(msg.unwrap_or_default());
+				locs.sort_by(cmp_location);
+				let o = self.locations_output(&locs, &mut texts);
+				if o.len() > 0 {
+					self.output = o.join("\n");
+				}
+			}
+			InlayHintRequest::METHOD => {
+				let msg = serde_json::from_str::<Option<Vec<InlayHint>>>(result.get())?;
+				if let Some(hw) = self.hints_win.as_mut() {
+					if hw.url == url {
+						let mut hints = msg.unwrap_or_default();
+						hints.sort_by_key(|h| h.position);
+						hw.hints = hints;
+					}
+				}
+			}
+			DocumentLinkRequest::METHOD => {
+				let msg = serde_json::from_str::<Option<Vec<DocumentLink>>>(result.get())?;
+				let mut links = msg.unwrap_or_default();
+				links.sort_by_key(|link| link.range.start);
+				self.set_hover(&url, |hover| {
+					hover.links = links;
+				});
+			}
+			DocumentLinkResolve::METHOD => {
+				let link = serde_json::from_str::<DocumentLink>(result.get())?;
+				if let Some(target) = &link.target {
+					plumb_link(target)?;
+				}
+			}
+			FoldingRangeRequest::METHOD => {
+				let msg = serde_json::from_str::<Option<Vec<FoldingRange>>>(result.get())?;
+				if let Some(fw) = self.folds_win.as_mut() {
+					if fw.url == url {
+						fw.set_folds(msg.unwrap_or_default());
+					}
+				}
+			}
+			InlayHintResolveRequest::METHOD => {
+				let hint = serde_json::from_str::<InlayHint>(result.get())?;
+				if let Some(hw) = self.hints_win.as_mut() {
+					let label = inlay_hint_label(&hint.label);
+					if let Some(h) = hw.hints.iter_mut().find(|h| {
+						h.position == hint.position && inlay_hint_label(&h.label) == label
+					}) {
+						*h = hint;
+					}
+				}
+			}
+			DocumentHighlightRequest::METHOD => {
+				let msg = serde_json::from_str::<Option<Vec<DocumentHighlight>>>(result.get())?;
 				if let Some(mut msg) = msg {
-					msg.sort_by(cmp_location);
-					let mut o = Vec::new();
-					let mut files: HashMap<Url, String> = HashMap::new();
-					for x in msg {
-						o.push(location_to_plumb(&x));
-						let text = files.entry(x.uri.clone()).or_insert_with(|| {
-							match self.get_sw_by_url(&x.uri) {
-								Some((_, win)) => win.text().unwrap_or((0, "".into())).1,
-								None => read_to_string(x.uri.path()).unwrap_or("".into()),
-							}
-						});
-						if let Some(line) = text.lines().nth(x.range.start.line.try_into().unwrap())
-						{
+					msg.sort_by_key(|h| h.range.start);
+					let text = self.file_text(&url);
+					let mut o = vec![];
+					for h in &msg {
+						let kind = match h.kind {
+							Some(DocumentHighlightKind::READ) => "Read",
+							Some(DocumentHighlightKind::WRITE) => "Write",
+							_ => "Text",
+						};
+						let loc = Location::new(url.clone(), h.range);
+						o.push(format!("{} {}", location_to_plumb(&loc, &text), kind));
+						if let Some(line) = text.lines().nth(h.range.start.line as usize) {
 							o.push(format!("\t{}", line.trim()));
 						}
 					}
-					if o.len() > 0 {
-						self.output = o.join("\n");
-					}
+					self.output = o.join("\n");
+					self.highlights = Some(HighlightSession {
+						url,
+						ranges: msg.into_iter().map(|h| h.range).collect(),
+						current: None,
+					});
 				}
 			}
 			DocumentSymbolRequest::METHOD => {
 				let msg = serde_json::from_str::<Option<DocumentSymbolResponse>>(result.get())?;
+				let mut o = vec![];
 				if let Some(msg) = msg {
-					let mut o: Vec<String> = vec![];
-					fn add_symbol(
-						o: &mut Vec<String>,
-						container: &Vec<String>,
-						name: &String,
-						kind: SymbolKind,
-						loc: &Location,
-					) {
-						o.push(format!(
-							"{}{} ({:?}): {}",
-							container
-								.iter()
-								.map(|c| format!("{}::", c))
-								.collect::<Vec<String>>()
-								.join(""),
-							name,
-							kind,
-							location_to_plumb(loc),
-						));
-					}
-					match msg.clone() {
-						DocumentSymbolResponse::Flat(sis) => {
-							for si in sis {
-								// Ignore variables in methods.
-								if si.container_name.as_ref().unwrap_or(&"".to_string()).len() == 0
-									&& si.kind == SymbolKind::VARIABLE
-								{
-									continue;
-								}
-								let cn = match si.container_name.clone() {
-									Some(c) => vec![c],
-									None => vec![],
-								};
-								add_symbol(&mut o, &cn, &si.name, si.kind, &si.location);
-							}
-						}
-						DocumentSymbolResponse::Nested(mut dss) => {
-							fn process(
-								url: &Url,
-								mut o: &mut Vec<String>,
-								parents: &Vec<String>,
-								dss: &mut Vec<DocumentSymbol>,
-							) {
-								dss.sort_by(|a, b| a.range.start.line.cmp(&b.range.start.line));
-								for ds in dss {
-									add_symbol(
-										&mut o,
-										parents,
-										&ds.name,
-										ds.kind,
-										&Location::new(url.clone(), ds.range),
-									);
-									if let Some(mut children) = ds.children.clone() {
-										let mut parents = parents.clone();
-										parents.push(ds.name.clone());
-										process(url, o, &parents, &mut children);
-									}
-								}
-							}
-							process(&url, &mut o, &vec![], &mut dss);
-						}
-					}
-					if o.len() > 0 {
-						self.output = o.join("\n");
-					}
+					let text = self.file_text(&url);
+					o = symbols_output(&url, &text, msg);
+				}
+				self.show_symbols(partial, o);
+			}
+			WorkspaceSymbolRequest::METHOD => {
+				let msg = serde_json::from_str::<Option<WorkspaceSymbolResponse>>(result.get())?;
+				let mut o = vec![];
+				if let Some(msg) = msg {
+					o = workspace_symbols_output(msg, |url| self.file_text(url));
 				}
+				self.show_symbols(partial, o);
 			}
 			SignatureHelpRequest::METHOD => {
 				let msg = serde_json::from_str::<Option<SignatureHelp>>(result.get())?;
-				if let Some(msg) = msg {
-					let sig = match msg.active_signature {
-						Some(i) => i,
-						None => 0,
-					};
-					self.set_hover(&url, |hover| {
-						hover.signature = msg.signatures.get(sig as usize).map(|sig| {
-							let mut s: String = sig.label.clone();
-							if let Some(doc) = &sig.documentation {
-								s.push_str("\n");
-								s.push_str(extract_doc(doc));
-							}
-							s
-						});
-					});
+				let msg = msg.filter(|msg| !msg.signatures.is_empty());
+				if let Some(state) = self.signature.as_mut() {
+					if state.url == url {
+						state.help = msg.clone();
+					}
 				}
+				self.set_hover(&url, |hover| {
+					hover.signature = msg.as_ref().map(format_signature_help);
+				});
 			}
 			CodeLensRequest::METHOD => {
 				let msg = serde_json::from_str::<Option<Vec<CodeLens>>>(result.get())?;
@@ -891,7 +2591,7 @@ impl Server {
 					} else {
 						self.set_hover(&url, |hover| {
 							for m in msg.iter().cloned() {
-								hover.code_actions.push(Action::Command(m));
+								hover.code_actions.push(m);
 							}
 						});
 					}
@@ -918,69 +2618,124 @@ impl Server {
 					});
 				}
 			}
+			ResolveCompletionItem::METHOD => {
+				let msg = serde_json::from_str::<CompletionItem>(result.get())?;
+				self.apply_completion(&client_id.client_name, url, msg)?;
+			}
+			ExecuteCommand::METHOD => {}
 			Formatting::METHOD => {
 				let msg = serde_json::from_str::<Option<Vec<TextEdit>>>(result.get())?;
 				if let Some(msg) = msg {
-					self.apply_text_edits(&url, InsertTextFormat::PLAIN_TEXT, &msg)?;
-					// Run any on put actions.
-					let actions = self
-						.config
-						.servers
-						.get(&client_id.client_name)
-						.unwrap()
-						.actions_on_put
-						.clone()
-						.unwrap_or(vec![]);
-					if !actions.is_empty() {
-						let id = self.send_request::<CodeActionRequest>(
-							&client_id.client_name,
-							url.clone(),
-							CodeActionParams {
-								text_document: TextDocumentIdentifier { uri: url },
-								range: Range::new(Position::new(0, 0), Position::new(0, 0)),
-								context: CodeActionContext {
-									diagnostics: vec![],
-									only: Some(actions),
-								},
-								work_done_progress_params: WorkDoneProgressParams {
-									work_done_token: None,
-								},
-								partial_result_params: PartialResultParams {
-									partial_result_token: None,
-								},
-							},
-						)?;
-						self.autorun.insert(id, ());
-					}
+					self.apply_text_edits(&url, &msg)?;
+					self.run_actions_on_put(&client_id.client_name, url)?;
 				}
 			}
 			GotoImplementation::METHOD => {
 				let msg = serde_json::from_str::<Option<GotoImplementationResponse>>(result.get())?;
 				if let Some(msg) = msg {
-					goto_definition(&msg)?;
+					self.goto(msg)?;
 				}
 			}
 			GotoTypeDefinition::METHOD => {
 				let msg = serde_json::from_str::<Option<GotoTypeDefinitionResponse>>(result.get())?;
 				if let Some(msg) = msg {
-					goto_definition(&msg)?;
+					self.goto(msg)?;
 				}
 			}
-			SemanticTokensRangeRequest::METHOD => {
-				let msg = serde_json::from_str::<Option<SemanticTokensRangeResult>>(result.get())?;
+			GotoDeclaration::METHOD => {
+				let msg = serde_json::from_str::<Option<GotoDeclarationResponse>>(result.get())?;
 				if let Some(msg) = msg {
-					match msg {
-						SemanticTokensRangeResult::Tokens(tokens) => {
-							// TODO: use the result_id and probably verify it with the send message?
-							// Not sure why there would be more than 1 result, but we only need to care
-							// about a single one anyway.
-							if let Some(token) = tokens.data.into_iter().next() {
-								self.set_hover(&url, |hover| {
-									hover.token = Some(
-										hover
-											.line
-											.chars()
-											.skip(token.delta_start as usize)
+					self.goto(msg)?;
+				}
+			}
+			DocumentDiagnosticRequest::METHOD => {
+				let msg = serde_json::from_str::<DocumentDiagnosticReportResult>(result.get())?;
+				let related = match msg {
+					DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(r)) => {
+						let report = r.full_document_diagnostic_report;
+						self.set_pulled_diagnostics(
+							&client_id.client_name,
+							url,
+							report.result_id,
+							Some(report.items),
+						);
+						r.related_documents
+					}
+					DocumentDiagnosticReportResult::Report(
+						DocumentDiagnosticReport::Unchanged(r),
+					) => {
+						let report = r.unchanged_document_diagnostic_report;
+						self.set_pulled_diagnostics(
+							&client_id.client_name,
+							url,
+							Some(report.result_id),
+							None,
+						);
+						r.related_documents
+					}
+					DocumentDiagnosticReportResult::Partial(r) => r.related_documents,
+				};
+				for (url, report) in related.unwrap_or_default() {
+					match report {
+						DocumentDiagnosticReportKind::Full(report) => self.set_pulled_diagnostics(
+							&client_id.client_name,
+							url,
+							report.result_id,
+							Some(report.items),
+						),
+						DocumentDiagnosticReportKind::Unchanged(report) => self
+							.set_pulled_diagnostics(
+								&client_id.client_name,
+								url,
+								Some(report.result_id),
+								None,
+							),
+					}
+				}
+			}
+			WorkspaceDiagnosticRequest::METHOD => {
+				let msg = serde_json::from_str::<WorkspaceDiagnosticReportResult>(result.get())?;
+				let items = match msg {
+					WorkspaceDiagnosticReportResult::Report(r) => r.items,
+					WorkspaceDiagnosticReportResult::Partial(r) => r.items,
+				};
+				for item in items {
+					match item {
+						WorkspaceDocumentDiagnosticReport::Full(r) => {
+							let report = r.full_document_diagnostic_report;
+							self.set_pulled_diagnostics(
+								&client_id.client_name,
+								r.uri,
+								report.result_id,
+								Some(report.items),
+							);
+						}
+						WorkspaceDocumentDiagnosticReport::Unchanged(r) => {
+							self.set_pulled_diagnostics(
+								&client_id.client_name,
+								r.uri,
+								Some(r.unchanged_document_diagnostic_report.result_id),
+								None,
+							);
+						}
+					}
+				}
+			}
+			SemanticTokensRangeRequest::METHOD => {
+				let msg = serde_json::from_str::<Option<SemanticTokensRangeResult>>(result.get())?;
+				if let Some(msg) = msg {
+					match msg {
+						SemanticTokensRangeResult::Tokens(tokens) => {
+							// TODO: use the result_id and probably verify it with the send message?
+							// Not sure why there would be more than 1 result, but we only need to care
+							// about a single one anyway.
+							if let Some(token) = tokens.data.into_iter().next() {
+								self.set_hover(&url, |hover| {
+									hover.token = Some(
+										hover
+											.line
+											.chars()
+											.skip(token.delta_start as usize)
 											.take(token.length as usize)
 											.collect(),
 									);
@@ -1008,27 +2763,21 @@ impl Server {
 			}
 			PublishDiagnostics::METHOD => {
 				let msg: PublishDiagnosticsParams = serde_json::from_str(params.unwrap().get())?;
-				let mut v = vec![];
-				let path = msg.uri.path();
-				// Cap diagnostic length.
-				for p in msg.diagnostics.iter().take(5) {
-					let msg = p.message.lines().next().unwrap_or("");
-					v.push(format!(
-						"{}:{}: [{:?}] {}",
-						path,
-						p.range.start.line + 1,
-						p.severity.unwrap_or(lsp_types::DiagnosticSeverity::ERROR),
-						msg,
-					));
-				}
-				self.diags.insert(path.to_string(), v);
+				self.set_diagnostics(msg.uri, msg.diagnostics);
 			}
 			ShowMessage::METHOD => {
 				let msg: ShowMessageParams = serde_json::from_str(params.unwrap().get())?;
 				self.output = format!("[{:?}] {}", msg.typ, msg.message);
 			}
 			Progress::METHOD => {
-				let msg: ProgressParams = serde_json::from_str(params.unwrap().get())?;
+				let params = params.unwrap();
+				let raw: RawProgress = serde_json::from_str(params.get())?;
+				if let NumberOrString::String(token) = &raw.token {
+					if self.partials.contains_key(token) {
+						return self.partial_progress(token, &raw.value);
+					}
+				}
+				let msg: ProgressParams = serde_json::from_str(params.get())?;
 				let name = format!("{}-{:?}", client_name, msg.token);
 				match &msg.value {
 					ProgressParamsValue::WorkDone(value) => match value {
@@ -1060,8 +2809,110 @@ impl Server {
 		}
 		Ok(())
 	}
-	fn lsp_request(&mut self, msg: lsp::DeMessage) -> Result<()> {
-		eprintln!("unknown request {:?}", msg);
+	fn lsp_request(&mut self, client_name: String, msg: lsp::DeMessage) -> Result<()> {
+		let id = msg.id.unwrap();
+		match msg.method.as_deref().unwrap() {
+			ShowMessageRequest::METHOD => {
+				let params: ShowMessageRequestParams =
+					serde_json::from_str(msg.params.unwrap().get())?;
+				self.message_requests.push(MessageRequest {
+					client_name,
+					id,
+					params,
+				});
+			}
+			ShowDocument::METHOD => {
+				let params: ShowDocumentParams = serde_json::from_str(msg.params.unwrap().get())?;
+				let success = self.show_document(params).is_ok();
+				self.clients
+					.get_mut(&client_name)
+					.unwrap()
+					.respond(id, ShowDocumentResult { success })?;
+			}
+			ApplyWorkspaceEdit::METHOD => {
+				let params: ApplyWorkspaceEditParams =
+					serde_json::from_str(msg.params.unwrap().get())?;
+				// Always reply so the server isn't left waiting.
+				let failure_reason = self
+					.apply_workspace_edit(&params.edit)
+					.err()
+					.map(|err| err.to_string());
+				self.clients.get_mut(&client_name).unwrap().respond(
+					id,
+					ApplyWorkspaceEditResponse {
+						applied: failure_reason.is_none(),
+						failure_reason,
+						failed_change: None,
+					},
+				)?;
+			}
+			InlayHintRefreshRequest::METHOD => {
+				self.clients
+					.get_mut(&client_name)
+					.unwrap()
+					.respond(id, Value::Null)?;
+				if matches!(&self.hints_win, Some(hw) if hw.client_name == client_name) {
+					self.request_hints()?;
+				}
+			}
+			WorkspaceDiagnosticRefresh::METHOD => {
+				self.clients
+					.get_mut(&client_name)
+					.unwrap()
+					.respond(id, Value::Null)?;
+				let urls: Vec<Url> = self
+					.ws
+					.values()
+					.filter_map(|ids| ids.values().next())
+					.filter(|sw| sw.client == client_name)
+					.map(|sw| sw.url.clone())
+					.collect();
+				for url in urls {
+					self.pull_diagnostics(&client_name, url)?;
+				}
+				if self.diag_win.is_some() {
+					self.pull_workspace_diagnostics()?;
+				}
+			}
+			_ => {
+				eprintln!("unknown request {:?}", msg);
+			}
+		}
+		Ok(())
+	}
+	/// Opens a document for window/showDocument. Documents that shouldn't take
+	/// focus are opened with a new window instead of plumbing, which would move
+	/// focus to it, or only have their dot set if they are already open.
+	fn show_document(&mut self, params: ShowDocumentParams) -> Result<()> {
+		if params.external.unwrap_or(false) || params.uri.scheme() != "file" {
+			return plumb_url(params.uri.to_string());
+		}
+		let path = params.uri.path();
+		metadata(path)?;
+		if !params.take_focus.unwrap_or(false) {
+			if let Some((_, sw)) = self.get_sw_by_url(&params.uri) {
+				if let Some(selection) = &params.selection {
+					sw.select(selection)?;
+				}
+				return Ok(());
+			}
+			let mut w = Win::new()?;
+			w.name(path)?;
+			w.ctl("get")?;
+			if let Some(selection) = &params.selection {
+				let text = read_to_string(path).unwrap_or_default();
+				select_in(&mut w, selection, &text)?;
+			}
+			return Ok(());
+		}
+		let loc = match params.selection {
+			Some(selection) => {
+				let text = self.file_text(&params.uri);
+				location_to_plumb(&Location::new(params.uri.clone(), selection), &text)
+			}
+			None => path.to_string(),
+		};
+		plumb_location(loc)?;
 		Ok(())
 	}
 	fn apply_workspace_edit(&mut self, edit: &WorkspaceEdit) -> Result<()> {
@@ -1082,11 +2933,7 @@ impl Server {
 							})
 							.cloned()
 							.collect();
-						self.apply_text_edits(
-							&edit.text_document.uri,
-							InsertTextFormat::PLAIN_TEXT,
-							&text_edits,
-						)?;
+						self.apply_text_edits(&edit.text_document.uri, &text_edits)?;
 					}
 				}
 				_ => panic!("unsupported document_changes {:?}", doc_changes),
@@ -1094,30 +2941,27 @@ impl Server {
 		}
 		if let Some(ref changes) = edit.changes {
 			for (url, edits) in changes {
-				self.apply_text_edits(&url, InsertTextFormat::PLAIN_TEXT, &edits)?;
+				self.apply_text_edits(&url, &edits)?;
 			}
 		}
 		Ok(())
 	}
-	fn apply_text_edits(
-		&mut self,
-		url: &Url,
-		format: InsertTextFormat,
-		edits: &Vec<TextEdit>,
-	) -> Result<()> {
+	/// Applies edits to url's window and returns the character offset at which each
+	/// edit's text starts once all of them are applied. Full file replacements
+	/// return no offsets.
+	fn apply_text_edits(&mut self, url: &Url, edits: &Vec<TextEdit>) -> Result<Vec<u32>> {
 		if edits.is_empty() {
-			return Ok(());
+			return Ok(vec![]);
 		}
 		let (_id, sw) = match self.get_sw_by_url(url) {
 			Some(v) => v,
-			None => return Ok(()),
+			None => return Ok(vec![]),
 		};
-		let mut body = String::new();
-		sw.w.read(File::Body)?.read_to_string(&mut body)?;
+		let body = sw.body()?;
 		let offsets = NlOffsets::new(std::io::Cursor::new(body.clone()))?;
 		if edits.len() == 1 {
 			if body == edits[0].new_text {
-				return Ok(());
+				return Ok(vec![]);
 			}
 			// Check if this is a full file replacement. If so, use a diff algorithm so acme doesn't scroll to the bottom.
 			let edit = edits[0].clone();
@@ -1142,35 +2986,35 @@ impl Server {
 						diff::Result::Both(_, _) => {}
 					}
 				}
-				return Ok(());
+				return Ok(vec![]);
 			}
 		}
+		let texts: Vec<&str> = edits.iter().map(|edit| edit.new_text.as_str()).collect();
 		sw.w.seek(File::Body, std::io::SeekFrom::Start(0))?;
 		sw.w.ctl("nomark")?;
 		sw.w.ctl("mark")?;
-		for edit in edits.iter().rev() {
-			let soff = offsets.line_to_offset(edit.range.start.line, edit.range.start.character);
-			let eoff = offsets.line_to_offset(edit.range.end.line, edit.range.end.character);
-			let addr = format!("#{},#{}", soff, eoff);
-			sw.w.addr(&addr)?;
-			match format {
-				InsertTextFormat::SNIPPET => {
-					lazy_static! {
-						static ref SNIPPET: Regex =
-							Regex::new(r"(\$\{\d+:[[:alpha:]]+\})|(\$0)").unwrap();
-					}
-					let text = &SNIPPET.replace_all(&edit.new_text, "");
-					sw.w.write(File::Data, text)?;
-					text.len()
-				}
-				InsertTextFormat::PLAIN_TEXT => {
-					sw.w.write(File::Data, &edit.new_text)?;
-					edit.new_text.len()
-				}
-				_ => panic!("unexpected {:?}", format),
-			};
+		// Apply edits from the end of the file so earlier offsets stay valid. The sort
+		// is stable, so inserts at the same position keep their order.
+		let mut order: Vec<usize> = (0..edits.len()).collect();
+		order.sort_by(|&a, &b| cmp_range(&edits[a].range, &edits[b].range));
+		let mut addrs = vec![(0, 0); edits.len()];
+		let mut starts = vec![0; edits.len()];
+		// How much the edits before the current one move its start offset.
+		let mut shift: i64 = 0;
+		for &i in &order {
+			let range = &edits[i].range;
+			let soff = offsets.line_to_offset(range.start.line, range.start.character);
+			let eoff = offsets.line_to_offset(range.end.line, range.end.character);
+			addrs[i] = (soff, eoff);
+			starts[i] = (soff as i64 + shift) as u32;
+			shift += texts[i].chars().count() as i64 - (eoff - soff) as i64;
 		}
-		Ok(())
+		for &i in order.iter().rev() {
+			let (soff, eoff) = addrs[i];
+			sw.w.addr(&format!("#{},#{}", soff, eoff))?;
+			sw.w.write(File::Data, texts[i])?;
+		}
+		Ok(starts)
 	}
 	fn did_change(&mut self, name: String, wid: usize) -> Result<()> {
 		// Sometimes we are sending a DidChange before a DidOpen. Maybe this is because
@@ -1186,8 +3030,152 @@ impl Server {
 			None => return Ok(()),
 		};
 		let client = sw.client.clone();
+		let url = sw.url.clone();
 		let params = sw.change_params()?;
-		self.send_notification::<DidChangeTextDocument>(&client, params)
+		let text = &params.content_changes[0].text;
+		let changed_hints = match &self.hints_win {
+			Some(hw) => hw.url == url && &hw.text != text,
+			None => false,
+		};
+		let changed_folds = match &self.folds_win {
+			Some(fw) => fw.url == url && &fw.text != text,
+			None => false,
+		};
+		let changed_outline = self
+			.outline_wins
+			.iter()
+			.any(|ow| ow.url == url && &ow.text != text);
+		self.send_notification::<DidChangeTextDocument>(&client, params)?;
+		if changed_hints {
+			self.request_hints()?;
+		}
+		if changed_folds {
+			self.request_folds()?;
+		}
+		if changed_outline {
+			self.request_outline(&url)?;
+		}
+		self.pull_diagnostics(&client, url)
+	}
+	/// Requests the symbols for url's +Outline window.
+	fn request_outline(&mut self, url: &Url) -> Result<()> {
+		let text = self.file_text(url);
+		let ow = match self.outline_wins.iter_mut().find(|ow| &ow.url == url) {
+			Some(ow) => ow,
+			None => return Ok(()),
+		};
+		ow.text = text;
+		let client_name = ow.client_name.clone();
+		let id = self.send_request::<DocumentSymbolRequest>(
+			&client_name,
+			url.clone(),
+			DocumentSymbolParams {
+				text_document: TextDocumentIdentifier::new(url.clone()),
+				work_done_progress_params,
+				partial_result_params,
+			},
+		)?;
+		self.outline_requests
+			.insert(ClientId::new(&client_name, id));
+		Ok(())
+	}
+	/// Requests folding ranges for the file shown in the +Folds window.
+	fn request_folds(&mut self) -> Result<()> {
+		let (client_name, url) = match &self.folds_win {
+			Some(fw) => (fw.client_name.clone(), fw.url.clone()),
+			None => return Ok(()),
+		};
+		let text = self.file_text(&url);
+		if let Some(fw) = self.folds_win.as_mut() {
+			fw.text = text;
+		}
+		self.send_request::<FoldingRangeRequest>(
+			&client_name,
+			url.clone(),
+			FoldingRangeParams {
+				text_document: TextDocumentIdentifier::new(url),
+				work_done_progress_params,
+				partial_result_params,
+			},
+		)?;
+		Ok(())
+	}
+	/// Selects the first line of the next (or previous) fold at the same level
+	/// as dot in the +Folds window's file.
+	fn goto_fold(&mut self, next: bool) -> Result<()> {
+		let url = match &self.folds_win {
+			Some(fw) => fw.url.clone(),
+			None => return Ok(()),
+		};
+		let line = match self.get_sw_by_url(&url) {
+			Some((_, sw)) => sw.range()?.start.line,
+			None => return Ok(()),
+		};
+		let target = match self
+			.folds_win
+			.as_ref()
+			.and_then(|fw| fw.sibling(line, next))
+		{
+			Some(target) => target,
+			None => return Ok(()),
+		};
+		match self.get_sw_by_url(&url) {
+			Some((_, sw)) => sw.select(&Range::new(
+				Position::new(target, 0),
+				Position::new(target + 1, 0),
+			)),
+			None => Ok(()),
+		}
+	}
+	/// Resolves the tooltip of the hint at q0 in the +Hints window.
+	fn resolve_hint(&mut self, q0: usize) -> Result<()> {
+		let hw = match &self.hints_win {
+			Some(hw) => hw,
+			None => return Ok(()),
+		};
+		let hint = match hw.hint_at(q0) {
+			Some(i) => hw.hints[i].clone(),
+			None => return Ok(()),
+		};
+		let resolve = match self
+			.capabilities
+			.get(&hw.client_name)
+			.and_then(|caps| caps.inlay_hint_provider.as_ref())
+		{
+			Some(OneOf::Right(InlayHintServerCapabilities::Options(opts))) => opts.resolve_provider,
+			Some(OneOf::Right(InlayHintServerCapabilities::RegistrationOptions(opts))) => {
+				opts.inlay_hint_options.resolve_provider
+			}
+			_ => None,
+		};
+		if hint.tooltip.is_some() || !resolve.unwrap_or(false) {
+			return Ok(());
+		}
+		let (client_name, url) = (hw.client_name.clone(), hw.url.clone());
+		self.send_request::<InlayHintResolveRequest>(&client_name, url, hint)?;
+		Ok(())
+	}
+	/// Requests inlay hints for the whole file shown in the +Hints window.
+	fn request_hints(&mut self) -> Result<()> {
+		let (client_name, url) = match &self.hints_win {
+			Some(hw) => (hw.client_name.clone(), hw.url.clone()),
+			None => return Ok(()),
+		};
+		let text = self.file_text(&url);
+		let end = Position::new(text.lines().count() as u32, 0);
+		if let Some(hw) = self.hints_win.as_mut() {
+			hw.text = text;
+		}
+		self.send_request::<InlayHintRequest>(
+			&client_name,
+			url.clone(),
+			InlayHintParams {
+				work_done_progress_params,
+				text_document: TextDocumentIdentifier::new(url),
+				range: Range::new(Position::new(0, 0), end),
+			},
+		)?;
+		Ok(())
 	}
 	fn set_focus(&mut self, ev: LogEvent) -> Result<()> {
 		self.focus = ev.name.clone();
@@ -1205,7 +3193,10 @@ impl Server {
 			TextDocumentPositionParams::new(sw.doc_ident(), range.start);
 		let text_document = TextDocumentIdentifier::new(url.clone());
 		let line = sw.line()?;
+		let dot = sw.pos()?;
+		let text = sw.body()?;
 		drop(sw);
+		self.file_focus = Some(ev.name.clone());
 
 		self.current_hover = Some(WindowHover {
 			client_name: client_name.into(),
@@ -1213,9 +3204,13 @@ impl Server {
 			line,
 			token: None,
 			signature: None,
+			peek: None,
 			lens: vec![],
+			links: vec![],
 			completion: vec![],
 			code_actions: vec![],
+			range,
+			action_filter: None,
 			actions: vec![],
 			action_addrs: vec![],
 			hover: None,
@@ -1236,8 +3231,9 @@ impl Server {
 				text_document: text_document.clone(),
 				range,
 				context: CodeActionContext {
-					diagnostics: vec![],
+					diagnostics: self.diagnostics_in_range(&url, &range),
 					only: None,
+					trigger_kind: None,
 				},
 				work_done_progress_params,
 				partial_result_params,
@@ -1266,15 +3262,36 @@ impl Server {
 				range,
 			},
 		)?;
-		self.send_request::<SignatureHelpRequest>(
-			client_name,
-			url.clone(),
-			SignatureHelpParams {
-				context: None,
-				text_document_position_params: text_document_position_params.clone(),
-				work_done_progress_params,
-			},
-		)?;
+		let help = match self.signature.take() {
+			Some(state) if state.url == url => state.help,
+			_ => None,
+		};
+		self.signature = Some(SignatureState {
+			client_name: client_name.clone(),
+			url: url.clone(),
+			filename: ev.name.clone(),
+			id: ev.id,
+			dot,
+			text,
+			help,
+		});
+		self.request_signature_help(text_document_position_params.clone())?;
+		let links = self
+			.capabilities
+			.get(client_name)
+			.map(|caps| caps.document_link_provider.is_some())
+			.unwrap_or(false);
+		if links {
+			self.send_request::<DocumentLinkRequest>(
+				client_name,
+				url.clone(),
+				DocumentLinkParams {
+					text_document: text_document.clone(),
+					work_done_progress_params,
+					partial_result_params,
+				},
+			)?;
+		}
 		self.send_request::<CodeLensRequest>(
 			client_name,
 			url.clone(),
@@ -1286,6 +3303,63 @@ impl Server {
 		)?;
 		Ok(())
 	}
+	/// Requests signature help for self.signature, as a retrigger if it already
+	/// has help.
+	fn request_signature_help(
+		&mut self,
+		text_document_position_params: TextDocumentPositionParams,
+	) -> Result<()> {
+		let state = match &self.signature {
+			Some(state) => state,
+			None => return Ok(()),
+		};
+		let context = state.help.as_ref().map(|help| SignatureHelpContext {
+			trigger_kind: SignatureHelpTriggerKind::CONTENT_CHANGE,
+			trigger_character: None,
+			is_retrigger: true,
+			active_signature_help: Some(help.clone()),
+		});
+		let (client_name, url) = (state.client_name.clone(), state.url.clone());
+		self.send_request::<SignatureHelpRequest>(
+			&client_name,
+			url,
+			SignatureHelpParams {
+				context,
+				text_document_position_params,
+				work_done_progress_params,
+			},
+		)?;
+		Ok(())
+	}
+	/// Retriggers signature help if the cursor moved while it is shown.
+	fn check_signature(&mut self) -> Result<()> {
+		let (filename, id, dot) = match &self.signature {
+			Some(state) if state.help.is_some() => (state.filename.clone(), state.id, state.dot),
+			_ => return Ok(()),
+		};
+		let sw = match self.get_sw_by_name_id(&filename, &id) {
+			Some(sw) => sw,
+			None => {
+				self.signature = None;
+				return Ok(());
+			}
+		};
+		let pos = sw.pos()?;
+		if pos == dot {
+			return Ok(());
+		}
+		let params = sw.text_doc_pos()?;
+		let text = sw.body()?;
+		// Only resend the document if it was edited, not just when dot moved.
+		if matches!(&self.signature, Some(state) if state.text != text) {
+			self.did_change(filename, id)?;
+		}
+		if let Some(state) = self.signature.as_mut() {
+			state.dot = pos;
+			state.text = text;
+		}
+		self.request_signature_help(params)
+	}
 	fn run_event(&mut self, ev: Event, filename: &str) -> Result<()> {
 		let (id, sw) = match self.get_sw_by_name(filename) {
 			Some(v) => v,
@@ -1296,6 +3370,7 @@ impl Server {
 		let text_document_position_params = sw.text_doc_pos()?;
 		let text_document_position = text_document_position_params.clone();
 		let text_document = TextDocumentIdentifier::new(url.clone());
+		let word = sw.word()?;
 		drop(sw);
 		self.did_change(filename.to_string(), id)?;
 		match ev.text.as_str() {
@@ -1310,28 +3385,61 @@ impl Server {
 					},
 				)?;
 			}
-			"references" => {
-				self.send_request::<References>(
+			"declaration" => {
+				self.send_request::<GotoDeclaration>(
+					client_name,
+					url,
+					GotoDeclarationParams {
+						text_document_position_params,
+						work_done_progress_params,
+						partial_result_params,
+					},
+				)?;
+			}
+			"peek" => {
+				let id = self.send_request::<GotoDefinition>(
+					client_name,
+					url,
+					GotoDefinitionParams {
+						text_document_position_params,
+						work_done_progress_params,
+						partial_result_params,
+					},
+				)?;
+				self.peeks.insert(ClientId::new(client_name, id), None);
+			}
+			"doc" => {
+				let id = self.send_request::<HoverRequest>(
 					client_name,
 					url,
+					HoverParams {
+						text_document_position_params,
+						work_done_progress_params,
+					},
+				)?;
+				self.docs.insert(ClientId::new(client_name, id), word);
+			}
+			"references" => {
+				self.send_partial_request::<References>(client_name, url, None, |partial| {
 					ReferenceParams {
 						text_document_position,
 						work_done_progress_params,
-						partial_result_params,
+						partial_result_params: partial,
 						context: ReferenceContext {
 							include_declaration: true,
 						},
-					},
-				)?;
+					}
+				})?;
 			}
 			"symbols" => {
-				self.send_request::<DocumentSymbolRequest>(
+				self.send_partial_request::<DocumentSymbolRequest>(
 					client_name,
 					url,
-					DocumentSymbolParams {
+					None,
+					|partial| DocumentSymbolParams {
 						text_document,
 						work_done_progress_params,
-						partial_result_params,
+						partial_result_params: partial,
 					},
 				)?;
 			}
@@ -1346,6 +3454,66 @@ impl Server {
 					},
 				)?;
 			}
+			"expand" | "shrink" => {
+				self.select_range(filename, ev.text == "shrink")?;
+			}
+			"outline" => {
+				if !self.outline_wins.iter().any(|ow| ow.url == url) {
+					let ow = OutlineWin::new(client_name.clone(), url.clone(), self.aux_s.clone())?;
+					self.outline_wins.push(ow);
+				}
+				self.request_outline(&url)?;
+			}
+			"folds" => {
+				match self.folds_win.as_mut() {
+					Some(fw) => {
+						fw.client_name = client_name.clone();
+						fw.url = url;
+						fw.set_folds(vec![]);
+					}
+					None => {
+						self.folds_win =
+							Some(FoldsWin::new(client_name.clone(), url, self.aux_s.clone())?);
+					}
+				}
+				self.request_folds()?;
+			}
+			"hints" => {
+				match self.hints_win.as_mut() {
+					Some(hw) => {
+						hw.client_name = client_name.clone();
+						hw.url = url;
+						hw.hints.clear();
+					}
+					None => {
+						self.hints_win =
+							Some(HintsWin::new(client_name.clone(), url, self.aux_s.clone())?);
+					}
+				}
+				self.request_hints()?;
+			}
+			"highlight" => {
+				// [next] and [prev] now cycle the occurrences instead of tabstops.
+				if matches!(&self.snippet, Some(s) if s.url == url) {
+					self.snippet = None;
+				}
+				self.send_request::<DocumentHighlightRequest>(
+					client_name,
+					url,
+					DocumentHighlightParams {
+						text_document_position_params,
+						work_done_progress_params,
+						partial_result_params,
+					},
+				)?;
+			}
+			"next" | "prev" => {
+				let delta = if ev.text == "next" { 1 } else { -1 };
+				match &self.snippet {
+					Some(session) if session.url == url => self.move_tabstop(delta)?,
+					_ => self.cycle_highlight(delta)?,
+				}
+			}
 			"typedef" => {
 				self.send_request::<GotoTypeDefinition>(
 					client_name,
@@ -1383,26 +3551,16 @@ impl Server {
 	}
 	fn run_action(&mut self, client_name: &str, url: Url, action: Action) -> Result<()> {
 		match action {
+			Action::Filter(kind) => {
+				self.filter_code_actions(client_name, url, kind)?;
+			}
 			Action::Command(CodeActionOrCommand::Command(cmd)) => {
-				if let Some(args) = cmd.arguments {
-					for arg in args {
-						#[derive(Deserialize)]
-						#[serde(rename_all = "camelCase")]
-						struct ArgWorkspaceEdit {
-							workspace_edit: WorkspaceEdit,
-						}
-						match serde_json::from_value::<ArgWorkspaceEdit>(arg) {
-							Ok(v) => self.apply_workspace_edit(&v.workspace_edit)?,
-							Err(err) => {
-								eprintln!("json err {}", err);
-								continue;
-							}
-						}
-					}
-				}
+				self.run_command(client_name, url, cmd)?;
 			}
 			Action::Command(CodeActionOrCommand::CodeAction(action)) => {
-				if let Some(edit) = action.edit {
+				if let Some(disabled) = action.disabled {
+					self.output = format!("{}: {}", action.title, disabled.reason);
+				} else if let Some(edit) = action.edit {
 					self.apply_workspace_edit(&edit)?;
 				} else {
 					let _id = self.send_request::<CodeActionResolveRequest>(
@@ -1413,42 +3571,344 @@ impl Server {
 				}
 			}
 			Action::Completion(item) => {
-				let format = item
-					.insert_text_format
-					.unwrap_or(InsertTextFormat::PLAIN_TEXT);
-				if let Some(edit) = item.text_edit.clone() {
-					match edit {
-						CompletionTextEdit::Edit(edit) => {
-							return self.apply_text_edits(&url, format, &vec![edit])
-						}
-						CompletionTextEdit::InsertAndReplace(_) => {
-							eprintln!("InsertAndReplace not supported");
-							return Ok(());
-						}
-					}
+				let resolve = self
+					.capabilities
+					.get(client_name)
+					.and_then(|caps| caps.completion_provider.as_ref())
+					.and_then(|opts| opts.resolve_provider)
+					.unwrap_or(false);
+				if resolve {
+					self.send_request::<ResolveCompletionItem>(client_name, url, item)?;
+				} else {
+					self.apply_completion(client_name, url, item)?;
 				}
-				panic!("unsupported");
 			}
 			Action::CodeLens(lens) => {
 				// TODO: rust-analyzer complains about "code lens without data" here. If I set
 				// lens.data to Value::Null, it complains with some resolution error.
 				let _id = self.send_request::<CodeLensResolve>(client_name.into(), url, lens)?;
 			}
+			Action::Link(link) => {
+				if let Some(target) = &link.target {
+					return plumb_link(target);
+				}
+				let resolve = self
+					.capabilities
+					.get(client_name)
+					.and_then(|caps| caps.document_link_provider.as_ref())
+					.and_then(|opts| opts.resolve_provider)
+					.unwrap_or(false);
+				if resolve {
+					self.send_request::<DocumentLinkResolve>(client_name, url, link)?;
+				}
+			}
 		}
 		Ok(())
 	}
-	fn run_cmd(&mut self, ev: Event) -> Result<()> {
-		match ev.c2 {
-			'x' | 'X' => match ev.text.as_str() {
-				"Get" => {
-					//self.actions.clear();
-					self.output.clear();
-					self.sync_windows()?;
-					self.diags.clear();
-					self.current_hover = None;
-				}
-				_ => {
-					panic!("unexpected");
+	/// Applies a (resolved) completion item: its edit and any additional edits as a
+	/// single batch, then its command.
+	fn apply_completion(
+		&mut self,
+		client_name: &str,
+		url: Url,
+		item: CompletionItem,
+	) -> Result<()> {
+		let mut edit = match item.text_edit {
+			Some(CompletionTextEdit::Edit(edit)) => edit,
+			Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+				let mode = self
+					.config
+					.servers
+					.get(client_name)
+					.unwrap()
+					.completion_mode
+					.unwrap_or(CompletionMode::Insert);
+				let range = match mode {
+					CompletionMode::Insert => edit.insert,
+					CompletionMode::Replace => edit.replace,
+				};
+				TextEdit::new(range, edit.new_text)
+			}
+			None => {
+				// Without an edit, replace the word under the cursor.
+				let range = match self.get_sw_by_url(&url) {
+					Some((_, sw)) => sw.word_range()?,
+					None => return Ok(()),
+				};
+				TextEdit::new(range, item.insert_text.unwrap_or(item.label))
+			}
+		};
+		let mut snippet = None;
+		if item.insert_text_format == Some(InsertTextFormat::SNIPPET) {
+			let vars = match self.get_sw_by_url(&url) {
+				Some((_, sw)) => sw.snippet_vars(edit.range.start)?,
+				None => return Ok(()),
+			};
+			let s = snippet::parse(&edit.new_text, &vars);
+			edit.new_text = s.text.clone();
+			snippet = Some(s);
+		}
+		let mut edits = vec![edit];
+		edits.extend(item.additional_text_edits.unwrap_or_default());
+		let starts = self.apply_text_edits(&url, &edits)?;
+		if let (Some(s), Some(start)) = (snippet, starts.first()) {
+			let tabstops = s
+				.tabstops
+				.iter()
+				.map(|(a, b)| (start + *a as u32, start + *b as u32))
+				.collect::<Vec<_>>();
+			self.snippet = None;
+			if !tabstops.is_empty() {
+				self.snippet = Some(SnippetSession {
+					url: url.clone(),
+					tabstops,
+					current: 0,
+					len: 0,
+				});
+				self.move_tabstop(0)?;
+			}
+		}
+		if let Some(cmd) = item.command {
+			self.run_command(client_name, url, cmd)?;
+		}
+		Ok(())
+	}
+	/// Moves the snippet session delta tabstops and selects the new tabstop in the
+	/// file window. Moving past the last tabstop ends the session.
+	fn move_tabstop(&mut self, delta: i64) -> Result<()> {
+		let mut session = match self.snippet.take() {
+			Some(s) => s,
+			None => return Ok(()),
+		};
+		let (_, sw) = match self.get_sw_by_url(&session.url) {
+			Some(v) => v,
+			None => return Ok(()),
+		};
+		let len = sw.body()?.chars().count() as u32;
+		// Assume any edits since the last move were made in the current tabstop and
+		// shift the tabstops after it.
+		let change = len as i64 - session.len as i64;
+		if delta != 0 && change != 0 {
+			let (_, end) = session.tabstops[session.current];
+			let shift = |o: u32| (o as i64 + change).max(0) as u32;
+			for (i, t) in session.tabstops.iter_mut().enumerate() {
+				if i == session.current {
+					t.1 = shift(t.1).max(t.0);
+				} else if t.0 >= end {
+					*t = (shift(t.0), shift(t.1));
+				}
+			}
+		}
+		let next = session.current as i64 + delta;
+		if next >= session.tabstops.len() as i64 {
+			return Ok(());
+		}
+		session.current = next.max(0) as usize;
+		session.len = len;
+		let (start, end) = session.tabstops[session.current];
+		sw.w.addr(&format!("#{},#{}", start, end))?;
+		sw.w.ctl("dot=addr")?;
+		sw.w.ctl("show")?;
+		self.snippet = Some(session);
+		Ok(())
+	}
+	/// Selects the next (or previous, if delta is negative) occurrence from the
+	/// last [highlight], wrapping around. The first move is relative to dot.
+	fn cycle_highlight(&mut self, delta: i64) -> Result<()> {
+		let url = match &self.highlights {
+			Some(s) if !s.ranges.is_empty() => s.url.clone(),
+			_ => return Ok(()),
+		};
+		// Borrow ws and highlights separately so both can be mutated.
+		let id = match self.focus_id.get(url.path()) {
+			Some(id) => *id,
+			None => return Ok(()),
+		};
+		let sw = match self.ws.get_mut(url.path()) {
+			Some(ids) => match ids.get_mut(&id) {
+				Some(sw) => sw,
+				None => return Ok(()),
+			},
+			None => return Ok(()),
+		};
+		let session = self.highlights.as_mut().unwrap();
+		let n = session.ranges.len() as i64;
+		let next = match session.current {
+			Some(i) => (i as i64 + delta).rem_euclid(n) as usize,
+			None => {
+				let pos = sw.text_doc_pos()?.position;
+				let after = session.ranges.iter().position(|r| r.start > pos);
+				if delta > 0 {
+					after.unwrap_or(0)
+				} else {
+					let before = session.ranges.iter().rposition(|r| r.end < pos);
+					before.unwrap_or(n as usize - 1)
+				}
+			}
+		};
+		session.current = Some(next);
+		sw.select(&session.ranges[next])
+	}
+	/// Sets dot to the next larger (or, if shrink, smaller) range on the window's
+	/// selection stack, requesting the stack first if dot moved since the last
+	/// [expand] or [shrink].
+	fn select_range(&mut self, filename: &str, shrink: bool) -> Result<()> {
+		let id = match self.winid_by_name(filename) {
+			Some(id) => id,
+			None => return Ok(()),
+		};
+		// Borrow ws directly so selections can be borrowed at the same time.
+		let sw = match self.ws.get_mut(filename).and_then(|ids| ids.get_mut(&id)) {
+			Some(sw) => sw,
+			None => return Ok(()),
+		};
+		let dot = sw.range()?;
+		if let Some(stack) = self.selections.get_mut(&id) {
+			if stack.ranges[stack.current] == dot {
+				stack.current = if shrink {
+					stack.current.saturating_sub(1)
+				} else {
+					(stack.current + 1).min(stack.ranges.len() - 1)
+				};
+				return sw.select(&stack.ranges[stack.current]);
+			}
+		}
+		let client_name = sw.client.clone();
+		let url = sw.url.clone();
+		let msg_id = self.send_request::<SelectionRangeRequest>(
+			&client_name,
+			url.clone(),
+			SelectionRangeParams {
+				text_document: TextDocumentIdentifier::new(url),
+				positions: vec![dot.start],
+				work_done_progress_params,
+				partial_result_params,
+			},
+		)?;
+		self.selection_requests.insert(
+			ClientId::new(&client_name, msg_id),
+			(filename.to_string(), id, shrink),
+		);
+		Ok(())
+	}
+	/// Starts a window's selection stack from a selectionRange response and
+	/// selects the smallest range larger than dot, or if shrink the largest
+	/// range smaller than dot.
+	fn selection_response(
+		&mut self,
+		(filename, id, shrink): (String, usize, bool),
+		result: &serde_json::value::RawValue,
+	) -> Result<()> {
+		let msg = serde_json::from_str::<Option<Vec<SelectionRange>>>(result.get())?;
+		let mut sel = match msg.and_then(|v| v.into_iter().next()) {
+			Some(sel) => Some(Box::new(sel)),
+			None => return Ok(()),
+		};
+		let mut ranges: Vec<Range> = vec![];
+		while let Some(s) = sel {
+			if ranges.last() != Some(&s.range) {
+				ranges.push(s.range);
+			}
+			sel = s.parent;
+		}
+		let sw = match self.get_sw_by_name_id(&filename, &id) {
+			Some(sw) => sw,
+			None => return Ok(()),
+		};
+		let dot = sw.range()?;
+		let contains = |a: &Range, b: &Range| a.start <= b.start && b.end <= a.end;
+		let current = if shrink {
+			ranges
+				.iter()
+				.rposition(|r| contains(&dot, r) && *r != dot)
+				.unwrap_or(0)
+		} else {
+			ranges
+				.iter()
+				.position(|r| contains(r, &dot) && *r != dot)
+				.unwrap_or(ranges.len() - 1)
+		};
+		sw.select(&ranges[current])?;
+		self.selections
+			.insert(id, SelectionStack { ranges, current });
+		Ok(())
+	}
+	/// Executes cmd on the server if the server advertises it. Otherwise applies
+	/// any workspace edits in its arguments.
+	fn run_command(&mut self, client_name: &str, url: Url, cmd: Command) -> Result<()> {
+		let on_server = self
+			.capabilities
+			.get(client_name)
+			.and_then(|caps| caps.execute_command_provider.as_ref())
+			.map(|opts| opts.commands.contains(&cmd.command))
+			.unwrap_or(false);
+		if on_server {
+			self.send_request::<ExecuteCommand>(
+				client_name,
+				url,
+				ExecuteCommandParams {
+					command: cmd.command,
+					arguments: cmd.arguments.unwrap_or_default(),
+					work_done_progress_params,
+				},
+			)?;
+			return Ok(());
+		}
+		if let Some(args) = cmd.arguments {
+			for arg in args {
+				#[derive(Deserialize)]
+				#[serde(rename_all = "camelCase")]
+				struct ArgWorkspaceEdit {
+					workspace_edit: WorkspaceEdit,
+				}
+				match serde_json::from_value::<ArgWorkspaceEdit>(arg) {
+					Ok(v) => self.apply_workspace_edit(&v.workspace_edit)?,
+					Err(err) => {
+						eprintln!("json err {}", err);
+						continue;
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+	fn run_cmd(&mut self, ev: Event) -> Result<()> {
+		match ev.c2 {
+			'x' | 'X' => match ev.text.as_str() {
+				"Diagnostics" => {
+					if self.diag_win.is_none() {
+						self.diag_win = Some(DiagWin::new(self.aux_s.clone())?);
+					}
+					self.pull_workspace_diagnostics()?;
+				}
+				"back" => {
+					self.navigate(false)?;
+				}
+				"forward" => {
+					self.navigate(true)?;
+				}
+				"Get" => {
+					//self.actions.clear();
+					self.output.clear();
+					self.sync_windows()?;
+					self.diags.clear();
+					self.diag_locs.clear();
+					self.diag_result_ids.clear();
+					self.snippet = None;
+					self.highlights = None;
+					self.selections.clear();
+					for req in self.message_requests.drain(..) {
+						if let Some(client) = self.clients.get_mut(&req.client_name) {
+							client.respond(req.id, Value::Null)?;
+						}
+					}
+					self.current_hover = None;
+				}
+				text if text.split_whitespace().next() == Some("Symbols") => {
+					self.workspace_symbols(&cmd_arg(&ev))?;
+				}
+				_ => {
+					panic!("unexpected");
 				}
 			},
 			'L' => {
@@ -1464,6 +3924,19 @@ impl Server {
 						return self.run_event(ev, &name);
 					}
 				}
+				if let Some(&(_, _, i, j)) = self
+					.message_addrs
+					.iter()
+					.find(|(start, end, _, _)| *start as u32 <= ev.q0 && ev.q0 <= *end as u32)
+				{
+					let req = self.message_requests.remove(i);
+					let action = req.params.actions.and_then(|a| a.into_iter().nth(j));
+					return self
+						.clients
+						.get_mut(&req.client_name)
+						.unwrap()
+						.respond(req.id, action);
+				}
 				{
 					let mut action: Option<(String, Url, Action)> = None;
 					if let Some(hover) = self.current_hover.as_mut() {
@@ -1483,19 +3956,103 @@ impl Server {
 						}
 					}
 					if let Some((client_name, url, action)) = action {
-						self.set_hover(&url, |hover| {
-							hover.code_actions.clear();
-							hover.completion.clear();
-						});
+						if !action.keeps_list() {
+							self.set_hover(&url, |hover| {
+								hover.code_actions.clear();
+								hover.completion.clear();
+							});
+						}
 						return self.run_action(&client_name, url, action);
 					}
 				}
-				return plumb_location(ev.text);
+				return self.jump(ev.text);
 			}
 			_ => {}
 		}
 		Ok(())
 	}
+	fn run_aux_cmd(&mut self, id: usize, ev: Event) -> Result<()> {
+		let filename = self
+			.ws
+			.iter()
+			.find(|(_, ids)| ids.contains_key(&id))
+			.map(|(name, _)| name.clone());
+		if let Some(filename) = filename {
+			return self.put_file(filename, id);
+		}
+		if let Some(dw) = self.diag_win.as_mut() {
+			if dw.aux.w.id() == id {
+				match ev.c2 {
+					'x' | 'X' => {
+						if ev.text == "Del" {
+							self.diag_win = None;
+						} else {
+							dw.run_cmd(&ev)?;
+							if ev.text == "Get" {
+								self.pull_workspace_diagnostics()?;
+							}
+						}
+					}
+					'L' => return self.jump(ev.text),
+					_ => {}
+				}
+			}
+		}
+		if matches!(&self.hints_win, Some(hw) if hw.aux.w.id() == id) {
+			match ev.c2 {
+				'x' | 'X' if ev.text == "Del" => {
+					self.hints_win = None;
+				}
+				'x' | 'X' => self.request_hints()?,
+				'L' if ev.text.contains(':') => return self.jump(ev.text),
+				'L' => self.resolve_hint(ev.q0 as usize)?,
+				_ => {}
+			}
+		}
+		if let Some(idx) = self.outline_wins.iter().position(|ow| ow.aux.w.id() == id) {
+			match ev.c2 {
+				'x' | 'X' if ev.text == "Del" => {
+					self.outline_wins.remove(idx);
+				}
+				'x' | 'X' if ev.text == "Get" => {
+					let url = self.outline_wins[idx].url.clone();
+					self.request_outline(&url)?;
+				}
+				'x' | 'X' => self.outline_wins[idx].run_cmd(&ev)?,
+				'L' => return self.jump(ev.text),
+				_ => {}
+			}
+		}
+		if let Some(fw) = self.folds_win.as_mut() {
+			if fw.aux.w.id() == id {
+				match ev.c2 {
+					'x' | 'X' => match ev.text.as_str() {
+						"Del" => self.folds_win = None,
+						"Next" => self.goto_fold(true)?,
+						"Prev" => self.goto_fold(false)?,
+						_ => self.request_folds()?,
+					},
+					'L' => {
+						if let Some(addr) = fw.look(ev.q0 as usize) {
+							return self.jump(addr);
+						}
+					}
+					_ => {}
+				}
+			}
+		}
+		if let Some(idx) = self.doc_wins.iter().position(|dw| dw.w.id() == id) {
+			match ev.c2 {
+				'x' | 'X' if ev.text == "Del" => {
+					self.doc_wins.remove(idx);
+				}
+				'L' if ev.text.contains("://") => return plumb_url(ev.text),
+				'L' => return self.jump(ev.text),
+				_ => {}
+			}
+		}
+		Ok(())
+	}
 	fn cmd_put(&mut self, ev: LogEvent) -> Result<()> {
 		self.did_change(ev.name.clone(), ev.id)?;
 		let sw = match self.get_sw_by_name_id(&ev.name, &ev.id) {
@@ -1513,39 +4070,290 @@ impl Server {
 				text: None,
 			},
 		)?;
-		let capabilities = self.capabilities.get(client_name).unwrap();
-		if self
-			.config
+		self.pull_workspace_diagnostics()?;
+		// Puts acre didn't see first (like Putall) are formatted after the write.
+		if !self.presaved.remove(&ev.id) && self.format_on_put(client_name) {
+			self.send_request::<Formatting>(
+				client_name,
+				url.clone(),
+				self.formatting_params(client_name, url, text_document),
+			)?;
+		}
+		Ok(())
+	}
+	fn format_on_put(&self, client_name: &str) -> bool {
+		self.config
 			.servers
 			.get(client_name)
 			.unwrap()
 			.format_on_put
 			.unwrap_or(true)
-			&& capabilities.document_formatting_provider.is_some()
+			&& self
+				.capabilities
+				.get(client_name)
+				.unwrap()
+				.document_formatting_provider
+				.is_some()
+	}
+	fn formatting_params(
+		&self,
+		client_name: &str,
+		url: Url,
+		text_document: TextDocumentIdentifier,
+	) -> DocumentFormattingParams {
+		DocumentFormattingParams {
+			text_document,
+			options: self.formatting_options(client_name, &url),
+			work_done_progress_params: WorkDoneProgressParams {
+				work_done_token: None,
+			},
+		}
+	}
+	/// Handles Put executed in a file window: sends willSave, then applies any
+	/// willSaveWaitUntil and formatting edits before writing the file, so the
+	/// window is clean afterward.
+	fn put_file(&mut self, filename: String, wid: usize) -> Result<()> {
+		self.did_change(filename.clone(), wid)?;
+		let sw = match self.get_sw_by_name_id(&filename, &wid) {
+			Some(sw) => sw,
+			None => return Ok(()),
+		};
+		let client_name = sw.client.clone();
+		let text_document = sw.doc_ident();
+		let will_save = match &self
+			.capabilities
+			.get(&client_name)
+			.unwrap()
+			.text_document_sync
 		{
-			self.send_request::<Formatting>(
-				client_name,
+			Some(TextDocumentSyncCapability::Options(o)) => o.will_save.unwrap_or(false),
+			_ => false,
+		};
+		if will_save {
+			self.send_notification::<WillSaveTextDocument>(
+				&client_name,
+				WillSaveTextDocumentParams {
+					text_document,
+					reason: TextDocumentSaveReason::MANUAL,
+				},
+			)?;
+		}
+		self.save_step(filename, wid, SaveStep::WillSaveWaitUntil)
+	}
+	/// Runs the first pre-save step at or after step that the server supports.
+	/// Once none are left, writes the file.
+	fn save_step(&mut self, filename: String, wid: usize, step: SaveStep) -> Result<()> {
+		let sw = match self.get_sw_by_name_id(&filename, &wid) {
+			Some(sw) => sw,
+			None => return Ok(()),
+		};
+		let client_name = sw.client.clone();
+		let url = sw.url.clone();
+		let text_document = sw.doc_ident();
+		let will_save_wait_until = match &self
+			.capabilities
+			.get(&client_name)
+			.unwrap()
+			.text_document_sync
+		{
+			Some(TextDocumentSyncCapability::Options(o)) => o.will_save_wait_until.unwrap_or(false),
+			_ => false,
+		};
+		let (id, next) = if step <= SaveStep::WillSaveWaitUntil && will_save_wait_until {
+			let id = self.send_request::<WillSaveWaitUntil>(
+				&client_name,
 				url,
-				DocumentFormattingParams {
+				WillSaveTextDocumentParams {
 					text_document,
-					options: FormattingOptions {
-						tab_size: 4,
-						insert_spaces: false,
-						properties: HashMap::new(),
-						trim_trailing_whitespace: Some(true),
-						insert_final_newline: Some(true),
-						trim_final_newlines: Some(true),
+					reason: TextDocumentSaveReason::MANUAL,
+				},
+			)?;
+			(id, SaveStep::Formatting)
+		} else if step <= SaveStep::Formatting && self.format_on_put(&client_name) {
+			// Earlier edits changed the text.
+			self.did_change(filename.clone(), wid)?;
+			let params = self.formatting_params(&client_name, url.clone(), text_document);
+			let id = self.send_request::<Formatting>(&client_name, url, params)?;
+			(id, SaveStep::Put)
+		} else {
+			self.presaved.insert(wid);
+			if let Some(sw) = self.get_sw_by_name_id(&filename, &wid) {
+				if let Err(err) = sw.w.ctl("put") {
+					// No Put event will arrive to clear it.
+					self.presaved.remove(&wid);
+					return Err(err);
+				}
+			}
+			return Ok(());
+		};
+		self.saves.insert(
+			ClientId::new(&client_name, id),
+			PendingSave {
+				filename,
+				wid,
+				next,
+				deadline: Instant::now() + SAVE_TIMEOUT,
+			},
+		);
+		Ok(())
+	}
+	/// Applies the edits in a willSaveWaitUntil or formatting response and
+	/// continues saving.
+	fn save_response(
+		&mut self,
+		client_id: &ClientId,
+		url: Url,
+		save: PendingSave,
+		result: Option<Box<serde_json::value::RawValue>>,
+	) -> Result<()> {
+		let applied = self.apply_save_edits(client_id, url, save.next, result);
+		// Write the file even if the edits couldn't be applied.
+		self.save_step(save.filename, save.wid, save.next)?;
+		applied
+	}
+	fn apply_save_edits(
+		&mut self,
+		client_id: &ClientId,
+		url: Url,
+		next: SaveStep,
+		result: Option<Box<serde_json::value::RawValue>>,
+	) -> Result<()> {
+		let edits = match result {
+			Some(result) => serde_json::from_str::<Option<Vec<TextEdit>>>(result.get())?,
+			None => None,
+		};
+		if let Some(edits) = edits {
+			self.apply_text_edits(&url, &edits)?;
+		}
+		if next == SaveStep::Put {
+			self.run_actions_on_put(&client_id.client_name, url)?;
+		}
+		Ok(())
+	}
+	/// Writes the files of saves whose server hasn't responded in time.
+	fn expire_saves(&mut self) -> Result<()> {
+		let now = Instant::now();
+		let expired: Vec<ClientId> = self
+			.saves
+			.iter()
+			.filter(|(_, save)| save.deadline <= now)
+			.map(|(id, _)| id.clone())
+			.collect();
+		for client_id in expired {
+			let save = self.saves.remove(&client_id).unwrap();
+			self.requests.remove(&client_id);
+			self.output = format!(
+				"{}: no response before save, writing anyway",
+				client_id.client_name
+			);
+			self.save_step(save.filename, save.wid, SaveStep::Put)?;
+		}
+		Ok(())
+	}
+	/// Replaces the focused file's code actions with those of kind, or all of
+	/// them for None.
+	fn filter_code_actions(
+		&mut self,
+		client_name: &str,
+		url: Url,
+		kind: Option<CodeActionKind>,
+	) -> Result<()> {
+		let range = match &self.current_hover {
+			Some(hover) if hover.url == url => hover.range,
+			_ => return Ok(()),
+		};
+		self.set_hover(&url, |hover| {
+			hover.action_filter = kind.clone();
+			hover.code_actions.clear();
+		});
+		let diagnostics = self.diagnostics_in_range(&url, &range);
+		self.send_request::<CodeActionRequest>(
+			client_name,
+			url.clone(),
+			CodeActionParams {
+				text_document: TextDocumentIdentifier::new(url),
+				range,
+				context: CodeActionContext {
+					diagnostics,
+					only: kind.map(|kind| vec![kind]),
+					trigger_kind: None,
+				},
+				work_done_progress_params,
+				partial_result_params,
+			},
+		)?;
+		Ok(())
+	}
+	/// Requests the configured actions_on_put code actions, which are run when
+	/// they arrive.
+	fn run_actions_on_put(&mut self, client_name: &str, url: Url) -> Result<()> {
+		let actions = self
+			.config
+			.servers
+			.get(client_name)
+			.unwrap()
+			.actions_on_put
+			.clone()
+			.unwrap_or(vec![]);
+		if !actions.is_empty() {
+			let range = Range::new(Position::new(0, 0), Position::new(0, 0));
+			let diagnostics = self.diagnostics_in_range(&url, &range);
+			let id = self.send_request::<CodeActionRequest>(
+				client_name,
+				url.clone(),
+				CodeActionParams {
+					text_document: TextDocumentIdentifier { uri: url },
+					range,
+					context: CodeActionContext {
+						diagnostics,
+						only: Some(actions),
+						trigger_kind: None,
 					},
 					work_done_progress_params: WorkDoneProgressParams {
 						work_done_token: None,
 					},
+					partial_result_params: PartialResultParams {
+						partial_result_token: None,
+					},
 				},
 			)?;
+			self.autorun.insert(id, ());
 		}
 		Ok(())
 	}
+	/// Resolves the formatting options for url. Project overrides take precedence
+	/// over the server's settings, which take precedence over .editorconfig.
+	fn formatting_options(&self, client_name: &str, url: &Url) -> FormattingOptions {
+		let server = self.config.servers.get(client_name).unwrap();
+		let mut f = ConfigFormatting::default();
+		// Find the most specific project containing url.
+		if let Some(projects) = &server.projects {
+			let mut best = "";
+			for (uri, project) in projects {
+				if uri_contains(uri, url.as_str()) && uri.len() > best.len() {
+					best = uri;
+					f = project.formatting.clone().unwrap_or_default();
+				}
+			}
+		}
+		f = f.or(server.formatting.clone().unwrap_or_default());
+		match editorconfig::properties(Path::new(url.path())) {
+			Ok(p) => f = f.or(p.into()),
+			Err(err) => eprintln!("editorconfig err: {}", err),
+		}
+		FormattingOptions {
+			tab_size: f.tab_size.unwrap_or(4),
+			insert_spaces: f.insert_spaces.unwrap_or(false),
+			properties: HashMap::new(),
+			trim_trailing_whitespace: Some(f.trim_trailing_whitespace.unwrap_or(true)),
+			insert_final_newline: Some(f.insert_final_newline.unwrap_or(true)),
+			trim_final_newlines: Some(f.trim_final_newlines.unwrap_or(true)),
+		}
+	}
 	fn wait(&mut self) -> Result<()> {
 		let (sync_s, sync_r) = bounded(1);
+		// Acme doesn't report cursor movement, so poll for it.
+		let tick_r = tick(Duration::from_millis(250));
 
 		self.sync_windows()?;
 		// chan index -> (recv chan, self.clients index)
@@ -1556,6 +4364,8 @@ impl Server {
 		let sel_ev_r = sel.recv(&self.ev_r);
 		let sel_err_r = sel.recv(&self.err_r);
 		let sel_sync_r = sel.recv(&sync_r);
+		let sel_aux_r = sel.recv(&self.aux_r);
+		let sel_tick_r = sel.recv(&tick_r);
 		let mut clients = HashMap::new();
 
 		for (name, c) in &self.clients {
@@ -1571,6 +4381,8 @@ impl Server {
 			sel.recv(&self.ev_r);
 			sel.recv(&self.err_r);
 			sel.recv(&sync_r);
+			sel.recv(&self.aux_r);
+			sel.recv(&tick_r);
 			for (_, c) in &self.clients {
 				sel.recv(&c.msg_r);
 			}
@@ -1628,6 +4440,16 @@ impl Server {
 					let _ = sync_r.recv();
 					self.sync()?;
 				}
+				_ if index == sel_aux_r => {
+					let (id, ev) = self.aux_r.recv()?;
+					self.run_aux_cmd(id, ev)?;
+				}
+				_ if index == sel_tick_r => {
+					no_sync = true;
+					let _ = tick_r.recv();
+					self.expire_saves()?;
+					self.check_signature()?;
+				}
 				_ => {
 					let (ch, name) = clients.get(&index).unwrap();
 					let msg = ch.recv()?;
@@ -1652,31 +4474,67 @@ impl Drop for Server {
 	}
 }
 
-fn goto_definition(goto: &GotoDefinitionResponse) -> Result<()> {
-	let loc: &Location = match goto {
-		GotoDefinitionResponse::Array(locs) => match locs.len() {
-			0 => return Ok(()),
-			_ => &locs[0],
-		},
-		GotoDefinitionResponse::Scalar(loc) => &loc,
-		_ => panic!("unknown definition response: {:?}", goto),
-	};
-	let plumb = location_to_plumb(loc);
-	plumb_location(plumb)?;
+/// Returns a plumbable address for l that selects its range. text is the
+/// contents of l's file, used to convert columns to runes. Acme stops expanding
+/// a right clicked address at characters like ':', so the column is expressed as
+/// a character offset from the start of the line: line-#0+#col.
+fn location_to_plumb(l: &Location, text: &str) -> String {
+	let start = position_to_addr(&l.range.start, text);
+	if l.range.start == l.range.end {
+		format!("{}:{}", l.uri.path(), start)
+	} else {
+		format!(
+			"{}:{},{}",
+			l.uri.path(),
+			start,
+			position_to_addr(&l.range.end, text)
+		)
+	}
+}
+
+/// Sets the dot of w, whose body is text, to r and shows it.
+fn select_in(w: &mut Win, r: &Range, text: &str) -> Result<()> {
+	w.addr(&format!(
+		"{},{}",
+		position_to_addr(&r.start, text),
+		position_to_addr(&r.end, text)
+	))?;
+	w.ctl("dot=addr")?;
+	w.ctl("show")?;
 	Ok(())
 }
 
-fn location_to_plumb(l: &Location) -> String {
-	// Including the character here apparently isn't useful because the right click
-	// event from acme doesn't include it, only the line. Why is this?
-	format!("{}:{}", l.uri.path(), l.range.start.line + 1)
+/// Returns the acme address of p in text, the contents of its file.
+fn position_to_addr(p: &Position, text: &str) -> String {
+	let line = text.lines().nth(p.line as usize).unwrap_or("");
+	let character = utf16_to_runes(line, p.character);
+	if character == 0 {
+		format!("{}-#0", p.line + 1)
+	} else {
+		format!("{}-#0+#{}", p.line + 1, character)
+	}
+}
+
+/// Converts an LSP character offset in line, which counts UTF-16 code units, to
+/// the rune offset acme uses. Offsets past the end of line are counted as runes.
+fn utf16_to_runes(line: &str, character: u32) -> u32 {
+	let (mut units, mut runes) = (0, 0);
+	for c in line.chars() {
+		if units >= character {
+			return runes;
+		}
+		units += c.len_utf16() as u32;
+		runes += 1;
+	}
+	runes + character.saturating_sub(units)
 }
 
-fn plumb_location(loc: String) -> Result<()> {
+/// Plumbs loc to edit. Reports whether it was sent: nothing is sent if its
+/// path doesn't exist.
+fn plumb_location(loc: String) -> Result<bool> {
 	let path = loc.split(":").next().unwrap();
-	// Verify path exists. If not, do nothing.
 	if metadata(path).is_err() {
-		return Ok(());
+		return Ok(false);
 	}
 	let f = plumb::open("send", OpenMode::WRITE)?;
 	let msg = plumb::Message {
@@ -1684,7 +4542,54 @@ fn plumb_location(loc: String) -> Result<()> {
 		typ: "text".to_string(),
 		data: loc.into(),
 	};
-	return msg.send(f);
+	msg.send(f)?;
+	Ok(true)
+}
+
+/// Plumbs a document link target: files to edit, with any #L12 or #12,3 style
+/// fragment as an address, and everything else to web.
+fn plumb_link(target: &Url) -> Result<()> {
+	if target.scheme() != "file" {
+		return plumb_url(target.to_string());
+	}
+	lazy_static! {
+		static ref LINE: Regex = Regex::new(r"^L?(\d+)(?:[,:]C?(\d+))?$").unwrap();
+	}
+	let mut loc = target.path().to_string();
+	if let Some(caps) = target.fragment().and_then(|f| LINE.captures(f)) {
+		let line: u32 = caps[1].parse().unwrap_or(1);
+		let col: u32 = caps
+			.get(2)
+			.and_then(|c| c.as_str().parse().ok())
+			.unwrap_or(1);
+		let pos = Position::new(line.saturating_sub(1), col.saturating_sub(1));
+		write!(&mut loc, ":{}", position_to_addr(&pos, ""))?;
+	}
+	plumb_location(loc)?;
+	Ok(())
+}
+
+/// Reports whether text looked at in a doc window is a URL or an existing
+/// file:addr.
+fn is_doc_link(text: &str) -> bool {
+	if text.contains("://") {
+		return true;
+	}
+	match text.split_once(':') {
+		Some((path, _)) => metadata(path).is_ok(),
+		None => false,
+	}
+}
+
+/// Sends url to the web port.
+fn plumb_url(url: String) -> Result<()> {
+	let f = plumb::open("send", OpenMode::WRITE)?;
+	let msg = plumb::Message {
+		dst: "web".to_string(),
+		typ: "text".to_string(),
+		data: url.into(),
+	};
+	msg.send(f)
 }
 
 fn format_pct(pct: Option<u32>) -> String {
@@ -1708,6 +4613,12 @@ fn cmp_range(a: &Range, b: &Range) -> Ordering {
 	return cmp_position(&a.end, &b.end);
 }
 
+/// Reports whether a and b overlap or touch.
+fn range_overlaps(a: &Range, b: &Range) -> bool {
+	cmp_position(&a.start, &b.end) != Ordering::Greater
+		&& cmp_position(&b.start, &a.end) != Ordering::Greater
+}
+
 fn cmp_position(a: &Position, b: &Position) -> Ordering {
 	if a.line != b.line {
 		return a.line.cmp(&b.line);
@@ -1715,6 +4626,306 @@ fn cmp_position(a: &Position, b: &Position) -> Ordering {
 	return a.character.cmp(&b.character);
 }
 
+fn diagnostic_options(caps: &ServerCapabilities) -> Option<&DiagnosticOptions> {
+	match caps.diagnostic_provider.as_ref()? {
+		DiagnosticServerCapabilities::Options(opts) => Some(opts),
+		DiagnosticServerCapabilities::RegistrationOptions(opts) => Some(&opts.diagnostic_options),
+	}
+}
+
+/// Formats the active signature with its active parameter marked by «», then
+/// the parameter's documentation, the other overloads, and the signature's
+/// documentation.
+fn format_signature_help(help: &SignatureHelp) -> String {
+	let active = (help.active_signature.unwrap_or(0) as usize).min(help.signatures.len() - 1);
+	let sig = &help.signatures[active];
+	let param = sig
+		.active_parameter
+		.or(help.active_parameter)
+		.and_then(|i| sig.parameters.as_ref()?.get(i as usize));
+	let mut s = String::new();
+	match param.and_then(|p| parameter_bounds(&sig.label, &p.label)) {
+		Some((start, end)) => {
+			write!(
+				&mut s,
+				"{}«{}»{}",
+				&sig.label[..start],
+				&sig.label[start..end],
+				&sig.label[end..]
+			)
+			.unwrap();
+		}
+		None => s.push_str(&sig.label),
+	}
+	if help.signatures.len() > 1 {
+		write!(&mut s, " ({}/{})", active + 1, help.signatures.len()).unwrap();
+	}
+	if let Some(param) = param {
+		if let Some(doc) = &param.documentation {
+			write!(&mut s, "\n{}", extract_doc(doc).trim()).unwrap();
+		}
+	}
+	for (i, other) in help.signatures.iter().enumerate() {
+		if i != active {
+			write!(&mut s, "\n{}", other.label).unwrap();
+		}
+	}
+	if let Some(doc) = &sig.documentation {
+		write!(&mut s, "\n{}", extract_doc(doc).trim()).unwrap();
+	}
+	s
+}
+
+/// Returns the byte range of a parameter in a signature label. Label offsets are
+/// in UTF-16 code units.
+fn parameter_bounds(label: &str, param: &ParameterLabel) -> Option<(usize, usize)> {
+	match param {
+		ParameterLabel::Simple(p) if !p.is_empty() => {
+			// Skip the function name in case the parameter also appears there.
+			let from = label.find('(').unwrap_or(0);
+			let start = from + label[from..].find(p.as_str())?;
+			Some((start, start + p.len()))
+		}
+		ParameterLabel::Simple(_) => None,
+		ParameterLabel::LabelOffsets([start, end]) => {
+			let byte = |off: u32| {
+				let mut units = 0;
+				for (i, c) in label.char_indices() {
+					if units >= off {
+						return Some(i);
+					}
+					units += c.len_utf16() as u32;
+				}
+				if units >= off {
+					Some(label.len())
+				} else {
+					None
+				}
+			};
+			let (start, end) = (byte(*start)?, byte(*end)?);
+			if start <= end {
+				Some((start, end))
+			} else {
+				None
+			}
+		}
+	}
+}
+
+fn add_symbol(
+	o: &mut Vec<String>,
+	container: &[String],
+	name: &str,
+	kind: SymbolKind,
+	loc: &Location,
+	text: &str,
+) {
+	o.push(format!(
+		"{}{} ({:?}): {}",
+		container
+			.iter()
+			.map(|c| format!("{}::", c))
+			.collect::<Vec<String>>()
+			.join(""),
+		name,
+		kind,
+		location_to_plumb(loc, text),
+	));
+}
+
+/// Reports whether url is uri or a path under it. file:///a contains
+/// file:///a/b but not file:///ab.
+fn uri_contains(uri: &str, url: &str) -> bool {
+	match url.strip_prefix(uri) {
+		Some(rest) => rest.is_empty() || rest.starts_with('/') || uri.ends_with('/'),
+		None => false,
+	}
+}
+
+/// Returns the kind of a code action. Commands have none.
+fn action_kind(action: &CodeActionOrCommand) -> Option<&CodeActionKind> {
+	match action {
+		CodeActionOrCommand::CodeAction(action) => action.kind.as_ref(),
+		CodeActionOrCommand::Command(_) => None,
+	}
+}
+
+/// Reports whether kind is filter or nested in it, like refactor.extract in
+/// refactor.
+fn kind_matches(kind: &CodeActionKind, filter: &CodeActionKind) -> bool {
+	let (kind, filter) = (kind.as_str(), filter.as_str());
+	kind == filter || (kind.starts_with(filter) && kind[filter.len()..].starts_with('.'))
+}
+
+/// Orders code actions by kind, in the order each kind first appears, with
+/// preferred actions first within a kind.
+fn group_code_actions(actions: Vec<CodeActionOrCommand>) -> Vec<CodeActionOrCommand> {
+	let mut kinds: Vec<Option<CodeActionKind>> = vec![];
+	for a in &actions {
+		let kind = action_kind(a).cloned();
+		if !kinds.contains(&kind) {
+			kinds.push(kind);
+		}
+	}
+	let mut grouped = actions;
+	grouped.sort_by_key(|a| {
+		let kind = action_kind(a).cloned();
+		let preferred = match a {
+			CodeActionOrCommand::CodeAction(a) => a.is_preferred.unwrap_or(false),
+			CodeActionOrCommand::Command(_) => false,
+		};
+		(kinds.iter().position(|k| k == &kind), !preferred)
+	});
+	grouped
+}
+
+/// Returns an output line for each symbol in a document symbol response. text
+/// is the contents of url.
+fn symbols_output(url: &Url, text: &str, msg: DocumentSymbolResponse) -> Vec<String> {
+	let mut o: Vec<String> = vec![];
+	match msg {
+		DocumentSymbolResponse::Flat(sis) => {
+			for si in sis {
+				// Ignore variables in methods.
+				if si.container_name.as_ref().unwrap_or(&"".to_string()).len() == 0
+					&& si.kind == SymbolKind::VARIABLE
+				{
+					continue;
+				}
+				let cn = match si.container_name.clone() {
+					Some(c) => vec![c],
+					None => vec![],
+				};
+				add_symbol(&mut o, &cn, &si.name, si.kind, &si.location, text);
+			}
+		}
+		DocumentSymbolResponse::Nested(mut dss) => {
+			fn process(
+				url: &Url,
+				text: &str,
+				o: &mut Vec<String>,
+				parents: &Vec<String>,
+				dss: &mut Vec<DocumentSymbol>,
+			) {
+				dss.sort_by(|a, b| a.range.start.line.cmp(&b.range.start.line));
+				for ds in dss {
+					add_symbol(
+						o,
+						parents,
+						&ds.name,
+						ds.kind,
+						&Location::new(url.clone(), ds.range),
+						text,
+					);
+					if let Some(mut children) = ds.children.clone() {
+						let mut parents = parents.clone();
+						parents.push(ds.name.clone());
+						process(url, text, o, &parents, &mut children);
+					}
+				}
+			}
+			process(url, text, &mut o, &vec![], &mut dss);
+		}
+	}
+	o
+}
+
+/// Returns an output line for each symbol in a workspace symbol response.
+/// file_text returns the contents of a file.
+fn workspace_symbols_output(
+	msg: WorkspaceSymbolResponse,
+	mut file_text: impl FnMut(&Url) -> String,
+) -> Vec<String> {
+	let mut o: Vec<String> = vec![];
+	let mut files: HashMap<Url, String> = HashMap::new();
+	let mut add =
+		|o: &mut Vec<String>, cn: Vec<String>, name: &str, kind: SymbolKind, loc: &Location| {
+			let text = files
+				.entry(loc.uri.clone())
+				.or_insert_with(|| file_text(&loc.uri));
+			add_symbol(o, &cn, name, kind, loc, text);
+		};
+	match msg {
+		WorkspaceSymbolResponse::Flat(sis) => {
+			for si in sis {
+				let cn: Vec<String> = si.container_name.into_iter().collect();
+				add(&mut o, cn, &si.name, si.kind, &si.location);
+			}
+		}
+		WorkspaceSymbolResponse::Nested(wss) => {
+			for ws in wss {
+				let loc = match ws.location {
+					OneOf::Left(loc) => loc,
+					OneOf::Right(wl) => Location::new(wl.uri, Range::default()),
+				};
+				let cn: Vec<String> = ws.container_name.into_iter().collect();
+				add(&mut o, cn, &ws.name, ws.kind, &loc);
+			}
+		}
+	}
+	o
+}
+
+/// Returns a one character icon for a symbol kind.
+fn symbol_icon(kind: SymbolKind) -> &'static str {
+	match kind {
+		SymbolKind::FILE => "▤",
+		SymbolKind::MODULE | SymbolKind::NAMESPACE | SymbolKind::PACKAGE => "◫",
+		SymbolKind::CLASS | SymbolKind::STRUCT => "◆",
+		SymbolKind::INTERFACE => "◇",
+		SymbolKind::ENUM => "∈",
+		SymbolKind::ENUM_MEMBER => "∙",
+		SymbolKind::METHOD | SymbolKind::FUNCTION | SymbolKind::CONSTRUCTOR => "ƒ",
+		SymbolKind::FIELD | SymbolKind::PROPERTY => "▪",
+		SymbolKind::VARIABLE => "χ",
+		SymbolKind::CONSTANT => "π",
+		SymbolKind::TYPE_PARAMETER => "τ",
+		SymbolKind::OPERATOR => "±",
+		_ => "•",
+	}
+}
+
+fn inlay_hint_label(label: &InlayHintLabel) -> String {
+	match label {
+		InlayHintLabel::String(s) => s.clone(),
+		InlayHintLabel::LabelParts(parts) => parts.iter().map(|p| p.value.as_str()).collect(),
+	}
+}
+
+/// Returns the tooltips of a hint and its label parts as plain text.
+fn inlay_hint_tooltips(hint: &InlayHint) -> Vec<String> {
+	fn markup(mc: &MarkupContent) -> String {
+		match mc.kind {
+			MarkupKind::Markdown => markdown::render(&mc.value),
+			MarkupKind::PlainText => mc.value.clone(),
+		}
+	}
+	let mut tooltips = vec![];
+	match &hint.tooltip {
+		Some(InlayHintTooltip::String(s)) => tooltips.push(s.clone()),
+		Some(InlayHintTooltip::MarkupContent(mc)) => tooltips.push(markup(mc)),
+		None => {}
+	}
+	if let InlayHintLabel::LabelParts(parts) = &hint.label {
+		for part in parts {
+			match &part.tooltip {
+				Some(InlayHintLabelPartTooltip::String(s)) => tooltips.push(s.clone()),
+				Some(InlayHintLabelPartTooltip::MarkupContent(mc)) => tooltips.push(markup(mc)),
+				None => {}
+			}
+		}
+	}
+	tooltips
+}
+
+/// Returns ms as markdown, fencing language strings.
+fn marked_string_markdown(ms: &MarkedString) -> String {
+	match ms {
+		MarkedString::String(s) => s.clone(),
+		MarkedString::LanguageString(ls) => format!("```{}\n{}\n```", ls.language, ls.value),
+	}
+}
+
 fn extract_doc(d: &Documentation) -> &str {
 	match d {
 		Documentation::String(s) => s,
@@ -1730,3 +4941,163 @@ const work_done_progress_params: WorkDoneProgressParams = WorkDoneProgressParams
 const partial_result_params: PartialResultParams = PartialResultParams {
 	partial_result_token: None,
 };
+
+#[cfg(test)]
+mod tests {
+	use crate::*;
+
+	#[test]
+	fn uri_contains_paths() {
+		let cases = vec![
+			("file:///a", "file:///a", true),
+			("file:///a", "file:///a/b.rs", true),
+			("file:///a/", "file:///a/b.rs", true),
+			("file:///a", "file:///ab", false),
+			("file:///a", "file:///ab/c.rs", false),
+			("file:///a/b", "file:///a", false),
+		];
+		for (uri, url, expect) in cases {
+			assert_eq!(uri_contains(uri, url), expect, "{} {}", uri, url);
+		}
+	}
+
+	#[test]
+	fn utf16_columns() {
+		let cases = vec![
+			("abc", 0, 0),
+			("abc", 2, 2),
+			("abc", 5, 5),
+			("é=1", 2, 2),
+			("𝄞x", 2, 1),
+			("𝄞x", 3, 2),
+			("a𝄞b𝄞", 4, 3),
+		];
+		for (line, character, expect) in cases {
+			assert_eq!(
+				utf16_to_runes(line, character),
+				expect,
+				"{} {}",
+				line,
+				character
+			);
+		}
+	}
+
+	#[test]
+	fn signature_parameters() {
+		let simple = |s: &str| ParameterLabel::Simple(s.to_string());
+		let cases = vec![
+			("a(a: i32)", simple("a"), Some((2, 3))),
+			("f(x, y)", simple("y"), Some((5, 6))),
+			("f(x)", simple("z"), None),
+			("f(x)", simple(""), None),
+			(
+				"f(x, y)",
+				ParameterLabel::LabelOffsets([5, 6]),
+				Some((5, 6)),
+			),
+			(
+				"f(é, y)",
+				ParameterLabel::LabelOffsets([5, 6]),
+				Some((6, 7)),
+			),
+			(
+				"f(𝄞, y)",
+				ParameterLabel::LabelOffsets([6, 7]),
+				Some((8, 9)),
+			),
+			("f(x)", ParameterLabel::LabelOffsets([3, 9]), None),
+			("f(x)", ParameterLabel::LabelOffsets([3, 2]), None),
+		];
+		for (label, param, expect) in cases {
+			assert_eq!(
+				parameter_bounds(label, &param),
+				expect,
+				"{} {:?}",
+				label,
+				param
+			);
+		}
+	}
+
+	#[test]
+	fn signature_help() {
+		let sig = |label: &str, params: &[&str]| SignatureInformation {
+			label: label.to_string(),
+			documentation: None,
+			parameters: Some(
+				params
+					.iter()
+					.map(|p| ParameterInformation {
+						label: ParameterLabel::Simple(p.to_string()),
+						documentation: None,
+					})
+					.collect(),
+			),
+			active_parameter: None,
+		};
+		let help = SignatureHelp {
+			signatures: vec![sig("f(x, y)", &["x", "y"]), sig("f(x)", &["x"])],
+			active_signature: Some(0),
+			active_parameter: Some(1),
+		};
+		assert_eq!(format_signature_help(&help), "f(x, «y») (1/2)\nf(x)");
+		let help = SignatureHelp {
+			signatures: vec![sig("g()", &[])],
+			active_signature: Some(3),
+			active_parameter: Some(0),
+		};
+		assert_eq!(format_signature_help(&help), "g()");
+	}
+
+	#[test]
+	fn code_action_kinds() {
+		let kind = |s: &str| CodeActionKind::from(s.to_string());
+		let cases = vec![
+			("refactor", "refactor", true),
+			("refactor.extract", "refactor", true),
+			("refactor.extract.function", "refactor.extract", true),
+			("refactorx", "refactor", false),
+			("refactor", "refactor.extract", false),
+			("quickfix", "refactor", false),
+		];
+		for (k, filter, expect) in cases {
+			assert_eq!(
+				kind_matches(&kind(k), &kind(filter)),
+				expect,
+				"{} {}",
+				k,
+				filter
+			);
+		}
+	}
+
+	#[test]
+	fn code_action_groups() {
+		let action = |title: &str, kind: &str, preferred: bool| {
+			CodeActionOrCommand::CodeAction(CodeAction {
+				title: title.to_string(),
+				kind: Some(CodeActionKind::from(kind.to_string())),
+				is_preferred: Some(preferred),
+				..Default::default()
+			})
+		};
+		let command =
+			CodeActionOrCommand::Command(Command::new("c".to_string(), "cmd".to_string(), None));
+		let actions = vec![
+			action("a", "quickfix", false),
+			command,
+			action("b", "refactor", false),
+			action("c", "quickfix", true),
+			action("d", "refactor", true),
+		];
+		let titles: Vec<String> = group_code_actions(actions)
+			.iter()
+			.map(|a| match a {
+				CodeActionOrCommand::CodeAction(a) => a.title.clone(),
+				CodeActionOrCommand::Command(c) => format!("cmd {}", c.title),
+			})
+			.collect();
+		assert_eq!(titles, vec!["c", "a", "cmd c", "d", "b"]);
+	}
+}
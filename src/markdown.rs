@@ -0,0 +1,127 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+/// Renders markdown as plain text for reading in acme. Code blocks lose their
+/// fences and common indentation, headers are underlined, and links become
+/// their text followed by the URL so the URL can be plumbed.
+pub fn render(s: &str) -> String {
+	let mut out = String::new();
+	let mut code: Option<Vec<&str>> = None;
+	for line in s.lines() {
+		let trimmed = line.trim_start();
+		if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+			match code.take() {
+				Some(lines) => push_code(&mut out, &lines),
+				None => code = Some(vec![]),
+			}
+			continue;
+		}
+		if let Some(lines) = code.as_mut() {
+			lines.push(line);
+			continue;
+		}
+		if let Some(header) = header(trimmed) {
+			let (level, text) = header;
+			let text = render_inline(text);
+			let underline = if level == 1 { "=" } else { "-" };
+			out.push_str(&text);
+			out.push('\n');
+			out.push_str(&underline.repeat(text.chars().count()));
+			out.push('\n');
+			continue;
+		}
+		out.push_str(&render_inline(line));
+		out.push('\n');
+	}
+	// An unterminated fence runs to the end.
+	if let Some(lines) = code {
+		push_code(&mut out, &lines);
+	}
+	out
+}
+
+/// Returns the level and text of an ATX header line.
+fn header(line: &str) -> Option<(usize, &str)> {
+	let level = line.chars().take_while(|&c| c == '#').count();
+	if level == 0 || level > 6 {
+		return None;
+	}
+	let rest = &line[level..];
+	if !rest.is_empty() && !rest.starts_with(' ') {
+		return None;
+	}
+	Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+fn push_code(out: &mut String, lines: &[&str]) {
+	let indent = lines
+		.iter()
+		.filter(|l| !l.trim().is_empty())
+		.map(|l| l.len() - l.trim_start().len())
+		.min()
+		.unwrap_or(0);
+	for line in lines {
+		out.push_str(line.get(indent..).unwrap_or("").trim_end());
+		out.push('\n');
+	}
+}
+
+/// Renders links, inline code, and emphasis in a line of text.
+fn render_inline(line: &str) -> String {
+	lazy_static! {
+		static ref LINK: Regex =
+			Regex::new(r#"!?\[([^\]]*)\]\(([^)\s]+)(?:\s+"[^"]*")?\)"#).unwrap();
+		static ref AUTOLINK: Regex = Regex::new(r"<([a-zA-Z][a-zA-Z0-9+.-]*://[^>\s]+)>").unwrap();
+		static ref CODE: Regex = Regex::new(r"`([^`]+)`").unwrap();
+		static ref STRONG: Regex = Regex::new(r"\*\*([^*]+)\*\*").unwrap();
+	}
+	let line = LINK.replace_all(line, |caps: &Captures| {
+		let text = caps[1].trim_matches('`');
+		let url = &caps[2];
+		if text.is_empty() || text == url {
+			url.to_string()
+		} else {
+			format!("{} ({})", text, url)
+		}
+	});
+	let line = AUTOLINK.replace_all(&line, "$1");
+	let line = CODE.replace_all(&line, "$1");
+	STRONG.replace_all(&line, "$1").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::markdown::*;
+
+	#[test]
+	fn render_markdown() {
+		let md = "```rust
+    pub fn new() -> Vec<T>
+```
+---
+# Examples
+
+Constructs a new, empty [`Vec<T>`](https://doc.rust-lang.org/std/vec/struct.Vec.html).
+See <https://go.dev> and **`len`**.
+
+```
+  let v = Vec::new();
+    v.len();
+```
+## Panics ##";
+		let want = "pub fn new() -> Vec<T>
+---
+Examples
+========
+
+Constructs a new, empty Vec<T> (https://doc.rust-lang.org/std/vec/struct.Vec.html).
+See https://go.dev and len.
+
+let v = Vec::new();
+  v.len();
+Panics
+------
+";
+		assert_eq!(render(md), want);
+	}
+}
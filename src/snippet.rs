@@ -0,0 +1,442 @@
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+/// An expanded snippet.
+#[derive(Debug, PartialEq)]
+pub struct Snippet {
+	/// The text of the snippet with placeholder defaults inserted.
+	pub text: String,
+	/// (start, end) character offsets into text of each tabstop, in navigation
+	/// order: 1, 2, ..., then 0.
+	pub tabstops: Vec<(usize, usize)>,
+}
+
+/// Values for snippet variables. Unknown or empty variables use their default
+/// text, or their name if they don't have one.
+#[derive(Debug, Default)]
+pub struct Variables {
+	pub filepath: String,
+	pub current_line: String,
+	pub current_word: String,
+	/// zero-based line number.
+	pub line_index: u32,
+}
+
+impl Variables {
+	fn get(&self, name: &str) -> Option<String> {
+		let path = Path::new(&self.filepath);
+		let v = match name {
+			"TM_FILEPATH" => self.filepath.clone(),
+			"TM_FILENAME" => path.file_name()?.to_string_lossy().into(),
+			"TM_FILENAME_BASE" => path.file_stem()?.to_string_lossy().into(),
+			"TM_DIRECTORY" => path.parent()?.to_string_lossy().into(),
+			"TM_CURRENT_LINE" => self.current_line.clone(),
+			"TM_CURRENT_WORD" => self.current_word.clone(),
+			"TM_LINE_INDEX" => self.line_index.to_string(),
+			"TM_LINE_NUMBER" => (self.line_index + 1).to_string(),
+			_ => return None,
+		};
+		if v.is_empty() {
+			None
+		} else {
+			Some(v)
+		}
+	}
+}
+
+/// Expands s according to the LSP snippet grammar. Malformed constructs are
+/// inserted literally.
+pub fn parse(s: &str, vars: &Variables) -> Snippet {
+	let mut p = Parser {
+		chars: s.chars().collect(),
+		pos: 0,
+		vars,
+		text: vec![],
+		stops: vec![],
+	};
+	p.parse_any(None);
+	let mut text = p.text;
+	let mut stops = p.stops;
+	// Tabstops without text mirror the text of a placeholder with the same
+	// number. Fill them from the end so earlier offsets stay valid.
+	let mut mirrors: Vec<usize> = (0..stops.len())
+		.filter(|&i| stops[i].1 == stops[i].2)
+		.collect();
+	mirrors.sort_by_key(|&i| std::cmp::Reverse(stops[i].1));
+	for i in mirrors {
+		let (n, at, _) = stops[i];
+		let value: Vec<char> = match stops
+			.iter()
+			.filter(|(m, s, e)| *m == n && s < e)
+			.min_by_key(|(_, s, _)| *s)
+		{
+			Some(&(_, s, e)) => text[s..e].to_vec(),
+			None => continue,
+		};
+		let len = value.len();
+		text.splice(at..at, value);
+		// Stops are pushed in text order except that placeholders come after
+		// the stops nested in them, so later stops at at follow or enclose it.
+		for (j, stop) in stops.iter_mut().enumerate() {
+			if j == i {
+				stop.2 += len;
+			} else if stop.1 > at || (stop.1 == at && stop.2 == at && j > i) {
+				stop.1 += len;
+				stop.2 += len;
+			} else if stop.2 > at || (stop.2 == at && j > i) {
+				stop.2 += len;
+			}
+		}
+	}
+	// Mirrored tabstops are visited once, at their first occurrence.
+	let mut seen = vec![];
+	stops.retain(|(n, _, _)| {
+		if seen.contains(n) {
+			false
+		} else {
+			seen.push(*n);
+			true
+		}
+	});
+	stops.sort_by_key(|(n, _, _)| if *n == 0 { u32::MAX } else { *n });
+	Snippet {
+		text: text.into_iter().collect(),
+		tabstops: stops.into_iter().map(|(_, s, e)| (s, e)).collect(),
+	}
+}
+
+struct Parser<'a> {
+	chars: Vec<char>,
+	pos: usize,
+	vars: &'a Variables,
+	text: Vec<char>,
+	/// (number, start, end)
+	stops: Vec<(u32, usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.pos).cloned()
+	}
+	fn eat(&mut self, c: char) -> bool {
+		if self.peek() == Some(c) {
+			self.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+	/// Parses until the end of input or an unescaped until, which is not consumed.
+	fn parse_any(&mut self, until: Option<char>) {
+		while let Some(c) = self.peek() {
+			if Some(c) == until {
+				return;
+			}
+			match c {
+				'\\' => {
+					self.pos += 1;
+					match self.peek() {
+						Some(c @ '$') | Some(c @ '}') | Some(c @ '\\') => {
+							self.pos += 1;
+							self.text.push(c);
+						}
+						_ => self.text.push('\\'),
+					}
+				}
+				'$' => {
+					let (start, len, nstops) = (self.pos, self.text.len(), self.stops.len());
+					self.pos += 1;
+					if !self.parse_dollar() {
+						self.pos = start + 1;
+						self.text.truncate(len);
+						self.stops.truncate(nstops);
+						self.text.push('$');
+					}
+				}
+				_ => {
+					self.pos += 1;
+					self.text.push(c);
+				}
+			}
+		}
+	}
+	/// Parses text until an unescaped character in ends, which is not consumed.
+	/// Returns None at the end of input.
+	fn parse_text(&mut self, ends: &[char], escapes: &[char]) -> Option<String> {
+		let mut s = String::new();
+		loop {
+			let c = self.peek()?;
+			if ends.contains(&c) {
+				return Some(s);
+			}
+			self.pos += 1;
+			if c == '\\' {
+				match self.peek() {
+					Some(e) if escapes.contains(&e) || e == '\\' => {
+						self.pos += 1;
+						s.push(e);
+					}
+					_ => s.push('\\'),
+				}
+			} else {
+				s.push(c);
+			}
+		}
+	}
+	/// Parses a transform's format string until its closing '/', which is not
+	/// consumed. Slashes inside ${...} items, like ${1:/upcase}, don't end it.
+	fn parse_format(&mut self) -> Option<String> {
+		let mut s = String::new();
+		let mut depth = 0;
+		loop {
+			let c = self.peek()?;
+			if c == '/' && depth == 0 {
+				return Some(s);
+			}
+			self.pos += 1;
+			match c {
+				'\\' if self.peek() == Some('/') => {
+					self.pos += 1;
+					s.push('/');
+					continue;
+				}
+				'{' if s.ends_with('$') => depth += 1,
+				'}' if depth > 0 => depth -= 1,
+				_ => {}
+			}
+			s.push(c);
+		}
+	}
+	fn parse_int(&mut self) -> Option<u32> {
+		let start = self.pos;
+		while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+			self.pos += 1;
+		}
+		self.chars[start..self.pos]
+			.iter()
+			.collect::<String>()
+			.parse()
+			.ok()
+	}
+	fn parse_var(&mut self) -> Option<String> {
+		let start = self.pos;
+		match self.peek() {
+			Some(c) if c == '_' || c.is_ascii_alphabetic() => self.pos += 1,
+			_ => return None,
+		}
+		while matches!(self.peek(), Some(c) if c == '_' || c.is_ascii_alphanumeric()) {
+			self.pos += 1;
+		}
+		Some(self.chars[start..self.pos].iter().collect())
+	}
+	/// Parses the construct after a '$'. Returns false if it is malformed.
+	fn parse_dollar(&mut self) -> bool {
+		if let Some(n) = self.parse_int() {
+			self.stops.push((n, self.text.len(), self.text.len()));
+			return true;
+		}
+		if let Some(var) = self.parse_var() {
+			let value = self.vars.get(&var).unwrap_or(var);
+			self.text.extend(value.chars());
+			return true;
+		}
+		if !self.eat('{') {
+			return false;
+		}
+		if let Some(n) = self.parse_int() {
+			let start = self.text.len();
+			if self.eat('}') {
+				self.stops.push((n, start, start));
+				return true;
+			}
+			if self.eat(':') {
+				self.parse_any(Some('}'));
+				if !self.eat('}') {
+					return false;
+				}
+				self.stops.push((n, start, self.text.len()));
+				return true;
+			}
+			if self.eat('|') {
+				let mut choices = vec![];
+				loop {
+					match self.parse_text(&[',', '|'], &[',', '|']) {
+						Some(choice) => choices.push(choice),
+						None => return false,
+					}
+					if self.eat('|') {
+						break;
+					}
+					self.eat(',');
+				}
+				if !self.eat('}') {
+					return false;
+				}
+				self.text.extend(choices[0].chars());
+				self.stops.push((n, start, self.text.len()));
+				return true;
+			}
+			return false;
+		}
+		let var = match self.parse_var() {
+			Some(var) => var,
+			None => return false,
+		};
+		let value = self.vars.get(&var);
+		if self.eat('}') {
+			self.text.extend(value.unwrap_or(var).chars());
+			return true;
+		}
+		if self.eat(':') {
+			let (len, nstops) = (self.text.len(), self.stops.len());
+			self.parse_any(Some('}'));
+			if !self.eat('}') {
+				return false;
+			}
+			if let Some(value) = value {
+				self.text.truncate(len);
+				self.stops.truncate(nstops);
+				self.text.extend(value.chars());
+			}
+			return true;
+		}
+		if self.eat('/') {
+			let re = match self.parse_text(&['/'], &['/']) {
+				Some(re) => re,
+				None => return false,
+			};
+			self.pos += 1;
+			let format = match self.parse_format() {
+				Some(format) => format,
+				None => return false,
+			};
+			self.pos += 1;
+			let options = match self.parse_text(&['}'], &[]) {
+				Some(options) => options,
+				None => return false,
+			};
+			self.pos += 1;
+			let value = value.unwrap_or_default();
+			let value = match Regex::new(&re) {
+				Ok(re) => {
+					let limit = if options.contains('g') { 0 } else { 1 };
+					re.replacen(&value, limit, |caps: &Captures| apply_format(&format, caps))
+						.into_owned()
+				}
+				Err(_) => value,
+			};
+			self.text.extend(value.chars());
+			return true;
+		}
+		false
+	}
+}
+
+/// Evaluates a transform format string, like "${1:/upcase}-$2", against caps.
+fn apply_format(format: &str, caps: &Captures) -> String {
+	lazy_static::lazy_static! {
+		static ref ITEM: Regex =
+			Regex::new(r"\$(\d+)|\$\{(\d+)\}|\$\{(\d+):(/upcase|/downcase|/capitalize|\+[^}]*|\?[^:}]*:[^}]*|-?[^}]*)\}")
+				.unwrap();
+	}
+	ITEM.replace_all(format, |item: &Captures| {
+		let group = item
+			.get(1)
+			.or_else(|| item.get(2))
+			.or_else(|| item.get(3))
+			.unwrap();
+		let m = group
+			.as_str()
+			.parse::<usize>()
+			.ok()
+			.and_then(|i| caps.get(i))
+			.map(|m| m.as_str())
+			.unwrap_or("");
+		let modifier = match item.get(4) {
+			Some(modifier) => modifier.as_str(),
+			None => return m.to_string(),
+		};
+		match modifier {
+			"/upcase" => m.to_uppercase(),
+			"/downcase" => m.to_lowercase(),
+			"/capitalize" => {
+				let mut c = m.chars();
+				match c.next() {
+					Some(first) => first.to_uppercase().chain(c).collect(),
+					None => "".to_string(),
+				}
+			}
+			_ if modifier.starts_with('+') => {
+				if m.is_empty() {
+					"".to_string()
+				} else {
+					modifier[1..].to_string()
+				}
+			}
+			_ if modifier.starts_with('?') => {
+				let mut sp = modifier[1..].splitn(2, ':');
+				let (yes, no) = (sp.next().unwrap_or(""), sp.next().unwrap_or(""));
+				if m.is_empty() { no } else { yes }.to_string()
+			}
+			_ => {
+				if m.is_empty() {
+					modifier.trim_start_matches('-').to_string()
+				} else {
+					m.to_string()
+				}
+			}
+		}
+	})
+	.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::snippet::*;
+
+	#[test]
+	fn snippets() {
+		let vars = Variables {
+			filepath: "/src/some_file.rs".to_string(),
+			..Default::default()
+		};
+		let cases = vec![
+			("foo($0)", "foo()", vec![(4, 4)]),
+			(
+				"foo(${1:&mut self}, $2)$0",
+				"foo(&mut self, )",
+				vec![(4, 13), (15, 15), (16, 16)],
+			),
+			(
+				"${1|a,b|} ${2:x ${3:y}}",
+				"a x y",
+				vec![(0, 1), (2, 5), (4, 5)],
+			),
+			("mod $TM_FILENAME_BASE;", "mod some_file;", vec![]),
+			("${UNKNOWN:default} $UNKNOWN", "default UNKNOWN", vec![]),
+			(
+				"${TM_FILENAME/(.*)\\.rs/${1:/upcase}/}",
+				"SOME_FILE",
+				vec![],
+			),
+			("cost \\$5 \\} $", "cost $5 } $", vec![]),
+			("${1:unterminated", "${1:unterminated", vec![]),
+			("ü${1:ö}", "üö", vec![(1, 2)]),
+			("${1:foo} = $1;$0", "foo = foo;", vec![(0, 3), (10, 10)]),
+			("$1 = ${1:foo}", "foo = foo", vec![(0, 3)]),
+			("${2:a$1} ${1:b}", "ab b", vec![(1, 2), (0, 2)]),
+		];
+		for (s, text, tabstops) in cases {
+			assert_eq!(
+				parse(s, &vars),
+				Snippet {
+					text: text.to_string(),
+					tabstops,
+				},
+				"{}",
+				s
+			);
+		}
+	}
+}